@@ -35,6 +35,14 @@ pub trait TextMap {
 
     /// Gets the value of `key'.
     fn get(&self, key: &str) -> Option<&str>;
+
+    /// Returns the keys in the map.
+    ///
+    /// This is used to extract the entries which have a common prefix (e.g., baggage items).
+    /// The default implementation returns an empty list.
+    fn keys(&self) -> Vec<&str> {
+        Vec::new()
+    }
 }
 impl<S: BuildHasher> TextMap for HashMap<String, String, S> {
     fn set(&mut self, key: &str, value: &str) {
@@ -43,6 +51,9 @@ impl<S: BuildHasher> TextMap for HashMap<String, String, S> {
     fn get(&self, key: &str) -> Option<&str> {
         HashMap::get(self, key).map(|v| v.as_ref())
     }
+    fn keys(&self) -> Vec<&str> {
+        HashMap::keys(self).map(|k| k.as_ref()).collect()
+    }
 }
 impl TextMap for BTreeMap<String, String> {
     fn set(&mut self, key: &str, value: &str) {
@@ -51,6 +62,9 @@ impl TextMap for BTreeMap<String, String> {
     fn get(&self, key: &str) -> Option<&str> {
         BTreeMap::get(self, key).map(|v| v.as_ref())
     }
+    fn keys(&self) -> Vec<&str> {
+        BTreeMap::keys(self).map(|k| k.as_ref()).collect()
+    }
 }
 
 /// This trait allows to inject `SpanContext` to HTTP header.
@@ -11,6 +11,9 @@ pub enum ErrorKind {
     /// Input data is invalid.
     InvalidInput,
 
+    /// A span context in a carrier (e.g., HTTP header) is malformed.
+    MalformedHeader,
+
     /// Other errors (e.g., I/O error).
     Other,
 }
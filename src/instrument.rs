@@ -0,0 +1,185 @@
+//! Span instrumentation for futures.
+//!
+//! Since a task of an async executor may move between threads at each `.await` point,
+//! activating a span for a thread (see the `scope` module) across `.await` points does not work.
+//! `Instrumented` instead activates the span only while the wrapped future is being polled.
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::instrument::Instrument;
+//! use rustracing::sampler::AllSampler;
+//! use rustracing::Tracer;
+//!
+//! let (tracer, _span_rx) = Tracer::new(AllSampler);
+//! let span = tracer.span("parent").start_with_state(());
+//! let future = async {
+//!     // `scoped_span` starts a child of the span being instrumented.
+//!     let _child = tracer.scoped_span("child").start_with_state(());
+//! }
+//! .instrument(span);
+//!
+//! // Runs `future` on any executor.
+//! # drop(future);
+//! ```
+use crate::scope;
+use crate::span::{Span, SpanHandle};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Extension trait for instrumenting futures with spans.
+pub trait Instrument: Future + Sized {
+    /// Wraps this future so that `span` is active while the future is being polled.
+    ///
+    /// The span is finished when the future completes or is dropped.
+    fn instrument<T>(self, span: Span<T>) -> Instrumented<Self, T>
+    where
+        T: Clone + 'static,
+    {
+        Instrumented::new(self, span)
+    }
+}
+impl<F: Future> Instrument for F {}
+
+/// Future instrumented with a span.
+///
+/// Each time this future is polled, the span is activated for the current thread
+/// (so `scope::active()` and `Tracer::scoped_span()` refer to it) until the inner poll returns.
+///
+/// The span is finished when the inner future completes.
+/// If this future is dropped before completion,
+/// the span is finished with the log `event: "cancelled"`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Instrumented<F, T> {
+    future: F,
+    span: Span<T>,
+    handle: SpanHandle<T>,
+}
+impl<F, T: Clone> Instrumented<F, T> {
+    /// Makes a new `Instrumented` instance.
+    pub fn new(future: F, span: Span<T>) -> Self {
+        let handle = span.handle();
+        Instrumented {
+            future,
+            span,
+            handle,
+        }
+    }
+
+    /// Returns the span which instruments the future.
+    pub fn span(&self) -> &Span<T> {
+        &self.span
+    }
+
+    /// Returns the mutable span which instruments the future.
+    pub fn span_mut(&mut self) -> &mut Span<T> {
+        &mut self.span
+    }
+}
+impl<F, T> Future for Instrumented<F, T>
+where
+    F: Future,
+    T: Clone + 'static,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // SAFETY: `future` is never moved out of `self` (it is structurally pinned)
+        // and the other fields are not pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        let _scope = scope::activate(this.handle.clone());
+        let result = future.poll(cx);
+        if result.is_ready() {
+            // Finishes the span.
+            this.span = Span::inactive();
+        }
+        result
+    }
+}
+impl<F, T> Drop for Instrumented<F, T> {
+    fn drop(&mut self) {
+        self.span.log(|log| {
+            log.std().event("cancelled");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::Tracer;
+    use std::ptr;
+    use std::task::{RawWaker, RawWakerVTable, Waker};
+
+    fn noop_waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(ptr::null(), &VTABLE)
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+    }
+
+    struct YieldOnce(bool);
+    impl Future for YieldOnce {
+        type Output = Option<SpanHandle<u32>>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+            if self.0 {
+                Poll::Ready(scope::active())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn instrumented_works() {
+        let (tracer, span_rx) = Tracer::new(AllSampler);
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let span = tracer.span("foo").start_with_state(1u32);
+        let mut future = Box::pin(YieldOnce(false).instrument(span));
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        assert!(scope::active::<u32>().is_none());
+        assert!(span_rx.try_recv().is_err());
+
+        let active = match future.as_mut().poll(&mut cx) {
+            Poll::Ready(active) => active.unwrap(),
+            Poll::Pending => panic!(),
+        };
+        assert_eq!(active.context().map(|c| *c.state()), Some(1));
+        assert!(scope::active::<u32>().is_none());
+
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(span.operation_name(), "foo");
+        assert!(span.logs().is_empty());
+        drop(future);
+        assert!(span_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn cancellation_is_logged() {
+        let (tracer, span_rx) = Tracer::new(AllSampler);
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let span = tracer.span("foo").start_with_state(1u32);
+        let mut future = Box::pin(YieldOnce(false).instrument(span));
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        drop(future);
+
+        let span = span_rx.try_recv().unwrap();
+        let fields = span.logs()[0].fields();
+        assert_eq!(fields[0].name(), "event");
+        assert_eq!(fields[0].value(), "cancelled");
+    }
+}
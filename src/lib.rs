@@ -52,14 +52,21 @@
 extern crate trackable;
 
 pub use crate::error::{Error, ErrorKind};
-pub use crate::tracer::Tracer;
+pub use crate::tracer::{BackpressurePolicy, Tracer, TracerBuilder};
 
 pub mod carrier;
 pub mod convert;
+pub mod instrument;
 pub mod log;
+pub mod panic;
+pub mod propagation;
+pub mod reporter;
 pub mod sampler;
+pub mod scope;
 pub mod span;
+pub mod state;
 pub mod tag;
+pub mod thread;
 
 mod error;
 mod tracer;
@@ -147,4 +154,178 @@ mod tests {
 
         assert!(span_rx.is_empty());
     }
+
+    #[test]
+    fn finish_at_works() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        let finish_time = std::time::UNIX_EPOCH + Duration::from_secs(10);
+        let span = tracer.span("foo").start_with_state(());
+        assert_eq!(span.finish_at(finish_time), span::FinishStatus::Sent);
+
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(span.finish_time(), finish_time);
+        assert!(span_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn span_limits_work() {
+        let limits = span::SpanLimits {
+            max_tags: 2,
+            max_logs: 1,
+            max_log_fields: 1,
+            max_baggage_items: 1,
+            max_value_len: 4,
+        };
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::builder(AllSampler, span_tx)
+            .span_limits(limits)
+            .build();
+        {
+            let mut span = tracer
+                .span("foo")
+                .tag(Tag::new("a", "\u{3042}\u{3044}"))
+                .tag(Tag::new("b", 1))
+                .tag(Tag::new("c", 2))
+                .start_with_state(());
+            span.set_tag(|| Tag::new("b", 3));
+            span.set_tag(|| Tag::new("d", 4));
+            span.set_tag(StdTag::error);
+            span.log(|log| {
+                log.field(("x", "0123456789")).field(("y", "1"));
+            });
+            span.log(|log| {
+                log.std().message("dropped");
+            });
+            span.set_baggage_item(|| span::BaggageItem::new("k1", "v"));
+            span.set_baggage_item(|| span::BaggageItem::new("k1", "w"));
+            span.set_baggage_item(|| span::BaggageItem::new("k2", "v"));
+        }
+
+        let span = span_rx.try_recv().unwrap();
+        let tags = span
+            .tags()
+            .iter()
+            .map(|t| (t.name(), t.value().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            [
+                ("a", tag::TagValue::from("\u{3042}")),
+                ("b", 3i64.into()),
+                ("error", true.into())
+            ]
+        );
+        assert_eq!(span.dropped_tags_count(), 2);
+        assert_eq!(span.logs().len(), 1);
+        assert_eq!(span.logs()[0].fields()[0].value(), "0123");
+        assert_eq!(span.dropped_logs_count(), 1);
+        assert_eq!(span.logs()[0].fields()[0].name(), "x");
+        assert_eq!(span.dropped_log_fields_count(), 1);
+        assert_eq!(span.truncated_values_count(), 2);
+        assert_eq!(span.context().baggage_items()[0].value(), "w");
+        assert_eq!(span.dropped_baggage_items_count(), 1);
+    }
+
+    #[test]
+    fn log_error_works() {
+        let (tracer, span_rx) = Tracer::new(AllSampler);
+        {
+            let mut span = tracer.span("foo").start_with_state(());
+            let error: Error = track!(Error::from(ErrorKind::InvalidInput));
+            span.log_error(&error);
+        }
+        let span = span_rx.try_recv().unwrap();
+        assert!(span.status().is_error());
+        let fields = span.logs()[0].fields();
+        let kind = fields.iter().find(|f| f.name() == "error.kind").unwrap();
+        assert_eq!(kind.value(), "rustracing::error::Error");
+        let message = fields.iter().find(|f| f.name() == "message").unwrap();
+        assert_eq!(message.value(), "InvalidInput");
+        assert!(fields.iter().any(|f| f.name() == "error.history"));
+    }
+
+    #[test]
+    fn log_boxed_error_works() {
+        let (tracer, span_rx) = Tracer::new(AllSampler);
+        {
+            let mut span = tracer.span("foo").start_with_state(());
+            let error: Box<dyn std::error::Error + Send + Sync> = "oops".into();
+            span.log_error(&*error);
+            let error: Box<dyn std::error::Error> = Box::new(Error::from(ErrorKind::Other));
+            span.log_error(&*error);
+        }
+        let span = span_rx.try_recv().unwrap();
+        let field = |i: usize, name| {
+            let fields = span.logs()[i].fields();
+            fields.iter().find(|f| f.name() == name).unwrap().clone()
+        };
+        assert_eq!(field(0, "message").value(), "oops");
+        assert_eq!(field(1, "message").value(), "Other");
+        assert_eq!(field(1, "error.kind").value(), "rustracing::error::Error");
+    }
+
+    #[test]
+    fn span_kind_and_status_work() {
+        use crate::span::{SpanKind, SpanStatus};
+
+        let (tracer, span_rx) = Tracer::new(AllSampler);
+        {
+            let mut span = tracer.span("foo").start_with_state(());
+            span.error_log(|log| {
+                log.message("failed");
+            });
+            span.set_kind(SpanKind::Server);
+        }
+        {
+            let mut span = tracer
+                .span("bar")
+                .tag(StdTag::span_kind("unknown"))
+                .start_with_state(());
+            span.set_status(SpanStatus::error("failed"));
+            span.set_status(SpanStatus::Ok);
+        }
+        {
+            let mut span = tracer.span("baz").start_with_state(());
+            span.set_status(SpanStatus::error("failed"));
+            span.set_status(SpanStatus::Unset);
+        }
+        {
+            let mut span = tracer.span("qux").start_with_state(());
+            span.set_tag(StdTag::error);
+        }
+
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(span.kind(), Some(SpanKind::Server));
+        assert_eq!(span.status(), &SpanStatus::error("failed"));
+        assert!(span.tags().iter().any(|t| t.name() == "error"));
+
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(span.kind(), None);
+        assert_eq!(span.status(), &SpanStatus::Ok);
+        assert!(!span.tags().iter().any(|t| t.name() == "error"));
+
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(span.status(), &SpanStatus::Unset);
+        assert!(!span.tags().iter().any(|t| t.name() == "error"));
+
+        let span = span_rx.try_recv().unwrap();
+        assert!(span.status().is_error());
+        assert!(span.tags().iter().any(|t| t.name() == "error"));
+    }
+
+    #[test]
+    fn u64_sampling_priority_works() {
+        let (tracer, span_rx) = Tracer::new(sampler::NullSampler);
+        let _ = tracer
+            .span("foo")
+            .tag(Tag::new("sampling.priority", 1u64))
+            .start_with_state(());
+        let _ = tracer
+            .span("bar")
+            .tag(Tag::new("sampling.priority", 0u64))
+            .start_with_state(());
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "foo");
+        assert!(span_rx.try_recv().is_err());
+    }
 }
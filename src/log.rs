@@ -1,8 +1,14 @@
 //! Span log.
+use crate::tag::{TagArray, TagValue};
 #[cfg(feature = "stacktrace")]
 use backtrace::Backtrace;
 use std::borrow::Cow;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::sync::OnceLock;
 use std::time::SystemTime;
+use trackable::{History, Location, Trackable};
 
 /// Span log builder.
 #[derive(Debug)]
@@ -24,12 +30,12 @@ impl LogBuilder {
     }
 
     /// Returns a specialized builder for the standard log fields.
-    pub fn std(&mut self) -> StdLogFieldsBuilder {
+    pub fn std(&mut self) -> StdLogFieldsBuilder<'_> {
         StdLogFieldsBuilder(self)
     }
 
     /// Returns a specialized builder for the standard error log fields.
-    pub fn error(&mut self) -> StdErrorLogFieldsBuilder {
+    pub fn error(&mut self) -> StdErrorLogFieldsBuilder<'_> {
         self.field(LogField::new("event", "error"));
         StdErrorLogFieldsBuilder(self)
     }
@@ -41,6 +47,34 @@ impl LogBuilder {
         }
     }
 
+    /// Truncates the fields to at most `max` in the order of insertion.
+    ///
+    /// If there are fields which have the same name, only the last one is counted.
+    /// Returns the number of the dropped fields.
+    pub(crate) fn truncate_fields(&mut self, max: usize) -> usize {
+        let mut names = HashSet::new();
+        let mut fields = Vec::with_capacity(self.fields.len());
+        for field in self.fields.drain(..).rev() {
+            if names.insert(field.name.clone()) {
+                fields.push(field);
+            }
+        }
+        fields.reverse();
+        let dropped = fields.len().saturating_sub(max);
+        fields.truncate(max);
+        self.fields = fields;
+        dropped
+    }
+
+    /// Returns the value of the last `message` field.
+    pub(crate) fn message(&self) -> Option<Cow<'static, str>> {
+        self.fields
+            .iter()
+            .rev()
+            .find(|f| f.name() == "message")
+            .map(|f| Cow::Owned(f.value().to_owned()))
+    }
+
     pub(crate) fn finish(mut self) -> Option<Log> {
         if self.fields.is_empty() {
             None
@@ -72,24 +106,44 @@ impl Log {
     pub fn time(&self) -> SystemTime {
         self.time
     }
+
+    pub(crate) fn fields_mut(&mut self) -> &mut Vec<LogField> {
+        &mut self.fields
+    }
 }
 
 /// Span log field.
-#[derive(Debug, Clone)]
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::log::LogField;
+/// use rustracing::tag::TagValue;
+///
+/// let field = LogField::from(("message", "foo"));
+/// assert_eq!(field.value(), "foo");
+///
+/// let field = LogField::new("retries", 3u64);
+/// assert_eq!(field.value(), "3");
+/// assert_eq!(field.typed_value(), &TagValue::U64(3));
+/// ```
+#[derive(Clone)]
 pub struct LogField {
     name: Cow<'static, str>,
-    value: Cow<'static, str>,
+    value: TagValue,
+    text: OnceLock<String>,
 }
 impl LogField {
     /// Makes a new `LogField` instance.
     pub fn new<N, V>(name: N, value: V) -> Self
     where
         N: Into<Cow<'static, str>>,
-        V: Into<Cow<'static, str>>,
+        V: Into<TagValue>,
     {
         LogField {
             name: name.into(),
             value: value.into(),
+            text: OnceLock::new(),
         }
     }
 
@@ -99,14 +153,42 @@ impl LogField {
     }
 
     /// Returns the value of this field.
+    ///
+    /// Values other than strings are converted to their textual representations.
+    /// Use `typed_value()` to get the value as is.
     pub fn value(&self) -> &str {
-        self.value.as_ref()
+        if let Some(s) = self.value.as_str() {
+            s
+        } else {
+            self.text.get_or_init(|| self.value.to_text().into_owned())
+        }
+    }
+
+    /// Returns the typed value of this field.
+    pub fn typed_value(&self) -> &TagValue {
+        &self.value
+    }
+
+    /// Truncates the value to at most `max_len` bytes.
+    ///
+    /// Returns `true` if the value has been truncated.
+    pub(crate) fn truncate_value(&mut self, max_len: usize) -> bool {
+        self.text.take();
+        self.value.truncate(max_len)
+    }
+}
+impl fmt::Debug for LogField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LogField")
+            .field("name", &self.name)
+            .field("value", &self.value)
+            .finish()
     }
 }
 impl<N, V> From<(N, V)> for LogField
 where
     N: Into<Cow<'static, str>>,
-    V: Into<Cow<'static, str>>,
+    V: Into<TagValue>,
 {
     fn from((n, v): (N, V)) -> Self {
         LogField::new(n, v)
@@ -133,7 +215,7 @@ impl<'a> StdLogFieldsBuilder<'a> {
     where
         T: Into<Cow<'static, str>>,
     {
-        self.0.field(LogField::new("event", event));
+        self.0.field(LogField::new("event", event.into()));
         self
     }
 
@@ -146,7 +228,7 @@ impl<'a> StdLogFieldsBuilder<'a> {
     where
         T: Into<Cow<'static, str>>,
     {
-        self.0.field(LogField::new("message", message));
+        self.0.field(LogField::new("message", message.into()));
         self
     }
 
@@ -176,7 +258,7 @@ impl<'a> StdErrorLogFieldsBuilder<'a> {
     where
         T: Into<Cow<'static, str>>,
     {
-        self.0.field(LogField::new("error.kind", kind));
+        self.0.field(LogField::new("error.kind", kind.into()));
         self
     }
 
@@ -189,7 +271,63 @@ impl<'a> StdErrorLogFieldsBuilder<'a> {
     where
         T: Into<Cow<'static, str>>,
     {
-        self.0.field(LogField::new("message", message));
+        self.0.field(LogField::new("message", message.into()));
+        self
+    }
+
+    /// Adds the fields describing `error` and the chain of its sources.
+    ///
+    /// The following fields are added:
+    /// - `message`: the display string of `error`
+    /// - `error.sources`: the display strings of the sources of `error` in order
+    ///   (only if `error` has sources)
+    /// - `error.history`: the tracking history of `error` (only for `trackable` errors)
+    ///
+    /// The `error.kind` field is not added because the type of `error` is erased;
+    /// `Span::log_error()` sets it to the type name of the error.
+    ///
+    /// Errors of this crate and `trackable::error::Failure` are rendered specially:
+    /// the history part of their display strings is moved to the `error.history` field,
+    /// and their causes (which are not returned by `Error::source()`) are added as sources.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustracing::sampler::AllSampler;
+    /// use rustracing::Tracer;
+    /// use std::io;
+    ///
+    /// let (tracer, span_rx) = Tracer::new(AllSampler);
+    /// {
+    ///     let error = io::Error::new(io::ErrorKind::Other, "oops");
+    ///     let error: Box<dyn std::error::Error> = Box::new(error);
+    ///     let mut span = tracer.span("foo").start_with_state(());
+    ///     span.error_log(|log| {
+    ///         log.kind("io").error_chain(&*error);
+    ///     });
+    /// }
+    /// let span = span_rx.try_recv().unwrap();
+    /// let message = span.logs()[0].fields().iter().find(|f| f.name() == "message");
+    /// assert_eq!(message.unwrap().value(), "oops");
+    /// ```
+    pub fn error_chain(&mut self, error: &(dyn Error + 'static)) -> &mut Self {
+        self.message(error_message(error));
+        if let Some(history) = trackable_history(error) {
+            let events = history
+                .events()
+                .iter()
+                .map(|e| Cow::Owned(e.to_string()))
+                .collect();
+            self.0
+                .field(LogField::new("error.history", TagArray::String(events)));
+        }
+
+        let sources = source_messages(error);
+        if !sources.is_empty() {
+            let sources = sources.into_iter().map(Cow::Owned).collect();
+            self.0
+                .field(LogField::new("error.sources", TagArray::String(sources)));
+        }
         self
     }
 
@@ -200,4 +338,201 @@ impl<'a> StdErrorLogFieldsBuilder<'a> {
             .field(LogField::new("stack", format!("{:?}", Backtrace::new())));
         self
     }
+
+    #[cfg(feature = "stacktrace")]
+    pub(crate) fn stack_trace(&mut self, stack: String) -> &mut Self {
+        self.0.field(LogField::new("stack", stack));
+        self
+    }
+}
+
+/// Error which can be logged by `Span::log_error()`.
+///
+/// This is implemented for all sized error types and for `dyn Error` trait objects
+/// (e.g., `&*boxed_error` of `Box<dyn Error + Send + Sync>`).
+pub trait AsDynError {
+    /// Returns the type name of this error.
+    ///
+    /// For trait objects, this is the name of the trait object type
+    /// unless the concrete type is a known `trackable` error type.
+    fn type_name(&self) -> &'static str;
+
+    /// Returns this error as a trait object.
+    fn as_dyn_error(&self) -> &(dyn Error + 'static);
+}
+impl<E: Error + 'static> AsDynError for E {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<E>()
+    }
+
+    fn as_dyn_error(&self) -> &(dyn Error + 'static) {
+        self
+    }
+}
+macro_rules! impl_as_dyn_error {
+    ($($t:ty),*) => {
+        $(impl AsDynError for $t {
+            fn type_name(&self) -> &'static str {
+                dyn_type_name(self).unwrap_or(std::any::type_name::<$t>())
+            }
+
+            fn as_dyn_error(&self) -> &(dyn Error + 'static) {
+                self
+            }
+        })*
+    };
+}
+impl_as_dyn_error!(
+    dyn Error + 'static,
+    dyn Error + Send + 'static,
+    dyn Error + Send + Sync + 'static
+);
+
+fn dyn_type_name(error: &(dyn Error + 'static)) -> Option<&'static str> {
+    if error.is::<crate::Error>() {
+        Some(std::any::type_name::<crate::Error>())
+    } else if error.is::<trackable::error::Failure>() {
+        Some(std::any::type_name::<trackable::error::Failure>())
+    } else {
+        None
+    }
+}
+
+fn trackable_history<'a>(error: &'a (dyn Error + 'static)) -> Option<&'a History<Location>> {
+    if let Some(e) = error.downcast_ref::<crate::Error>() {
+        e.history()
+    } else if let Some(e) = error.downcast_ref::<trackable::error::Failure>() {
+        e.history()
+    } else {
+        None
+    }
+}
+
+/// Trackable errors expose their causes only via the deprecated `Error::cause()`.
+#[allow(deprecated)]
+fn trackable_cause<'a>(error: &'a (dyn Error + 'static)) -> Option<&'a dyn Error> {
+    if let Some(e) = error.downcast_ref::<crate::Error>() {
+        Error::cause(&**e)
+    } else if let Some(e) = error.downcast_ref::<trackable::error::Failure>() {
+        Error::cause(&**e)
+    } else {
+        None
+    }
+}
+
+fn error_message(error: &(dyn Error + 'static)) -> String {
+    let message = error.to_string();
+    if trackable_history(error).is_some() {
+        // The display string of a trackable error ends with its history.
+        if let Some(i) = message.find("\nHISTORY:") {
+            return message[..i].to_owned();
+        }
+    }
+    message
+}
+
+fn source_messages(error: &(dyn Error + 'static)) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut current = error;
+    while let Some(source) = current.source() {
+        messages.push(error_message(source));
+        current = source;
+    }
+    let mut cause = trackable_cause(current);
+    while let Some(e) = cause {
+        messages.push(e.to_string());
+        #[allow(deprecated)]
+        {
+            cause = e.cause();
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+    use std::fmt;
+    use trackable::error::ErrorKindExt;
+
+    #[derive(Debug)]
+    struct Wrapper(Option<Box<dyn Error + Send + Sync>>);
+    impl fmt::Display for Wrapper {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "wrapper")
+        }
+    }
+    impl Error for Wrapper {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.0.as_ref().map(|e| &**e as _)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Chained(String, Option<Box<dyn Error + Send + Sync>>);
+    impl fmt::Display for Chained {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+    impl Error for Chained {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.1.as_ref().map(|e| &**e as _)
+        }
+    }
+
+    fn chain_fields(error: &(dyn Error + 'static)) -> Vec<LogField> {
+        let mut builder = LogBuilder::new();
+        builder.error().error_chain(error);
+        builder.finish().unwrap().fields().to_vec()
+    }
+
+    #[test]
+    fn error_chain_works() {
+        let mut error = Wrapper(None);
+        for _ in 0..10 {
+            error = Wrapper(Some(Box::new(error)));
+        }
+        let fields = chain_fields(&error);
+        let names = fields.iter().map(|f| f.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["error.sources", "event", "message"]);
+        assert_eq!(fields[0].value(), ["wrapper"; 10].join(","));
+        assert_eq!(fields[2].value(), "wrapper");
+
+        let mut error: Option<Box<dyn Error + Send + Sync>> = None;
+        for i in (1..=11).rev() {
+            error = Some(Box::new(Chained(format!("source{}", i), error)));
+        }
+        let error = Wrapper(error);
+        let fields = chain_fields(&error);
+        let sources = (1..=11).map(|i| format!("source{}", i)).collect::<Vec<_>>();
+        assert_eq!(fields[0].value(), sources.join(","));
+    }
+
+    #[test]
+    fn trackable_error_chain_works() {
+        let cause = Wrapper(None);
+        let error: crate::Error = track!(crate::Error::from(ErrorKind::Other.cause(cause)));
+        let error = Wrapper(Some(Box::new(track!(error, "again"))));
+        let fields = chain_fields(&error);
+        assert_eq!(fields[0].name(), "error.sources");
+        assert_eq!(
+            fields[0].typed_value(),
+            &TagValue::from(vec!["Other (cause; wrapper)", "wrapper"])
+        );
+
+        let fields = chain_fields(error.source().unwrap());
+        assert_eq!(fields[0].name(), "error.history");
+        match fields[0].typed_value() {
+            TagValue::Array(TagArray::String(events)) => {
+                assert_eq!(events.len(), 2);
+                assert!(events[1].ends_with(" -- again"));
+            }
+            v => panic!("{:?}", v),
+        }
+        assert_eq!(fields[1].name(), "error.sources");
+        assert_eq!(fields[3].name(), "message");
+        assert_eq!(fields[3].value(), "Other (cause; wrapper)");
+    }
 }
@@ -0,0 +1,187 @@
+//! Panic hook for recording the details of panics in spans.
+//!
+//! When a thread panics, the spans dropped during unwinding get the `StdTag::error()` tag
+//! and an error log whose `error.kind` field is `"panic"`.
+//! Since the panic message is not available while unwinding,
+//! the log has the `message` field (and the `stack` field if the `stacktrace` feature is enabled)
+//! only if the hook installed by `install_hook()` has captured them.
+//! The hook captures the details only if sampled spans started on the panicking thread are alive,
+//! so panics unrelated to spans don't pay for capturing backtraces.
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::sampler::AllSampler;
+//! use rustracing::Tracer;
+//!
+//! rustracing::panic::install_hook();
+//!
+//! let (tracer, span_rx) = Tracer::new(AllSampler);
+//! let _ = std::panic::catch_unwind(|| {
+//!     let _span = tracer.span("foo").start_with_state(());
+//!     panic!("something wrong");
+//! });
+//!
+//! let span = span_rx.try_recv().unwrap();
+//! let fields = span.logs()[0].fields();
+//! assert!(fields.iter().any(|f| f.name() == "error.kind" && f.value() == "panic"));
+//! assert!(fields.iter().any(|f| f.name() == "message" && f.value().contains("something wrong")));
+//! ```
+#[cfg(feature = "stacktrace")]
+use backtrace::Backtrace;
+use std::cell::RefCell;
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Once};
+
+static HOOK_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<PanicDetails>> = const { RefCell::new(None) };
+    static LIVE_SPANS: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+}
+
+/// Installs the panic hook which captures the details of panics for spans.
+///
+/// The hook calls the previously installed hook after capturing the details.
+/// Calling this function more than once has no effect.
+pub fn install_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let has_live_spans = LIVE_SPANS
+                .try_with(|n| n.load(Ordering::SeqCst) > 0)
+                .unwrap_or(false);
+            if !has_live_spans {
+                previous_hook(info);
+                return;
+            }
+
+            let payload = if let Some(s) = info.payload().downcast_ref::<&str>() {
+                s
+            } else if let Some(s) = info.payload().downcast_ref::<String>() {
+                s.as_str()
+            } else {
+                "Box<dyn Any>"
+            };
+            let message = if let Some(location) = info.location() {
+                format!("panicked at {}: {}", location, payload)
+            } else {
+                format!("panicked: {}", payload)
+            };
+            let details = PanicDetails {
+                message,
+                #[cfg(feature = "stacktrace")]
+                stack: format!("{:?}", Backtrace::new()),
+            };
+            let _ = LAST_PANIC.try_with(|last| *last.borrow_mut() = Some(details));
+            previous_hook(info);
+        }));
+        HOOK_INSTALLED.store(true, Ordering::SeqCst);
+    });
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct PanicDetails {
+    pub message: String,
+    #[cfg(feature = "stacktrace")]
+    pub stack: String,
+}
+
+/// Returns the details of the last panic captured in the current thread.
+///
+/// The details are kept until all the spans started on the thread are dropped,
+/// so every span dropped during unwinding can refer to them.
+pub(crate) fn last_panic() -> Option<PanicDetails> {
+    LAST_PANIC
+        .try_with(|last| last.borrow().clone())
+        .ok()
+        .flatten()
+}
+
+/// Guard which indicates that a sampled span started on a thread is alive.
+///
+/// This is created only if the hook has been installed.
+#[derive(Debug)]
+pub(crate) struct SpanGuard(Arc<AtomicUsize>);
+impl SpanGuard {
+    pub(crate) fn new() -> Option<Self> {
+        if !HOOK_INSTALLED.load(Ordering::Relaxed) {
+            return None;
+        }
+        LIVE_SPANS
+            .try_with(|n| {
+                n.fetch_add(1, Ordering::SeqCst);
+                SpanGuard(Arc::clone(n))
+            })
+            .ok()
+    }
+}
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        if self.0.fetch_sub(1, Ordering::SeqCst) != 1 {
+            return;
+        }
+        let _ = LIVE_SPANS.try_with(|n| {
+            if Arc::ptr_eq(n, &self.0) {
+                let _ = LAST_PANIC.try_with(|last| last.borrow_mut().take());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::Tracer;
+
+    #[test]
+    fn panic_is_recorded() {
+        let (tracer, span_rx) = Tracer::new(AllSampler);
+        let _ = std::panic::catch_unwind(|| {
+            let _span = tracer.span("without_hook").start_with_state(());
+            panic!("first");
+        });
+        install_hook();
+        let _ = std::panic::catch_unwind(|| {
+            let mut span = tracer.span("with_hook").start_with_state(());
+            span.error_log(|log| {
+                log.kind("Other");
+            });
+            panic!("second");
+        });
+        assert!(last_panic().is_none());
+
+        // Panics without live spans are not captured.
+        let _ = std::panic::catch_unwind(|| panic!("third"));
+        assert!(last_panic().is_none());
+        let _ = tracer.span("ok").start_with_state(());
+
+        let span = span_rx.try_recv().unwrap();
+        assert!(span.tags().iter().any(|t| t.name() == "error"));
+        let fields = span.logs()[0].fields();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].name(), "error.kind");
+        assert_eq!(fields[0].value(), "panic");
+        assert_eq!(fields[1].name(), "event");
+
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(
+            span.tags().iter().filter(|t| t.name() == "error").count(),
+            1
+        );
+        assert_eq!(span.logs().len(), 2);
+        let fields = span.logs()[1].fields();
+        let message = fields.iter().find(|f| f.name() == "message").unwrap();
+        assert!(message.value().starts_with("panicked at src/panic.rs:"));
+        assert!(message.value().ends_with(": second"));
+        #[cfg(feature = "stacktrace")]
+        assert!(fields.iter().any(|f| f.name() == "stack"));
+
+        let span = span_rx.try_recv().unwrap();
+        assert!(span.tags().is_empty());
+        assert!(span.logs().is_empty());
+    }
+}
@@ -0,0 +1,420 @@
+use super::{Extractor, Injector, Propagator};
+use crate::span::SpanContext;
+use crate::state::{SpanContextState, TraceId, FLAG_DEBUG, FLAG_SAMPLED};
+use crate::{Error, ErrorKind, Result};
+use trackable::error::ErrorKindExt;
+
+const B3: &str = "b3";
+const TRACE_ID: &str = "x-b3-traceid";
+const SPAN_ID: &str = "x-b3-spanid";
+const PARENT_SPAN_ID: &str = "x-b3-parentspanid";
+const SAMPLED: &str = "x-b3-sampled";
+const FLAGS: &str = "x-b3-flags";
+
+/// [B3][b3] propagator.
+///
+/// By default, this injects the multiple `X-B3-*` fields.
+/// If `single_header(true)` is specified, it injects the single `b3` field instead.
+/// On extraction, the `b3` field takes precedence over the `X-B3-*` fields.
+///
+/// The debug flag (i.e., `X-B3-Flags: 1` or the sampling state `d`) is mapped onto
+/// `FLAG_DEBUG` of `SpanContextState` and `SpanContext::sampling_priority()`,
+/// so the spans which refer to the extracted context are always sampled.
+///
+/// If the sampling state is absent (i.e., neither `X-B3-Sampled` nor `X-B3-Flags` is present,
+/// or the `b3` field only has the trace and span identifiers),
+/// the extracted state is marked as deferred (see `SpanContextState::is_deferred()`),
+/// so `ParentBasedSampler` lets its root sampler decide.
+/// Such a state is injected without the sampling state as well.
+///
+/// A `b3` field which only has the sampling state (e.g., `b3: 0`) carries no span context,
+/// thus `extract` will return `Ok(None)` for it.
+///
+/// [b3]: https://github.com/openzipkin/b3-propagation
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::propagation::{B3Propagator, Propagator};
+/// use rustracing::sampler::NullSampler;
+/// use rustracing::state::SpanContextState;
+/// use rustracing::Tracer;
+/// use std::collections::HashMap;
+///
+/// let mut headers = HashMap::new();
+/// headers.insert(
+///     "b3".to_owned(),
+///     "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-d".to_owned(),
+/// );
+/// let context = B3Propagator::new()
+///     .extract_from_http_header(&headers)
+///     .unwrap()
+///     .unwrap();
+/// assert!(context.state().is_debug());
+///
+/// let (tracer, _span_rx) = Tracer::<_, SpanContextState>::new(NullSampler);
+/// let span = tracer.span("foo").child_of(&context).start();
+/// assert!(span.is_sampled());
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct B3Propagator {
+    single_header: bool,
+}
+impl B3Propagator {
+    /// Makes a new `B3Propagator` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to inject the single `b3` field instead of the multiple `X-B3-*` fields.
+    ///
+    /// The default value is `false`.
+    pub fn single_header(mut self, enabled: bool) -> Self {
+        self.single_header = enabled;
+        self
+    }
+
+    fn extract_single(value: &str) -> Result<Option<SpanContextState>> {
+        let parts = value.trim().split('-').collect::<Vec<_>>();
+        if parts.len() == 1 {
+            track_assert!(
+                matches!(parts[0], "0" | "1" | "d"),
+                ErrorKind::MalformedHeader; value
+            );
+            return Ok(None);
+        }
+        track_assert!(parts.len() <= 4, ErrorKind::MalformedHeader; value);
+
+        let trace_id = track!(parse_trace_id(parts[0]))?;
+        let span_id = track!(parse_span_id(parts[1]))?;
+        let flags = match parts.get(2).copied() {
+            None => None,
+            Some("0") => Some(0),
+            Some("1") => Some(FLAG_SAMPLED),
+            Some("d") => Some(FLAG_SAMPLED | FLAG_DEBUG),
+            Some(_) => track_panic!(ErrorKind::MalformedHeader; value),
+        };
+        let parent_span_id = if let Some(s) = parts.get(3) {
+            track!(parse_span_id(s))?
+        } else {
+            0
+        };
+        Ok(Some(
+            SpanContextState::new(trace_id, span_id)
+                .with_parent_span_id(parent_span_id)
+                .with_flags(flags.unwrap_or(0))
+                .with_remote(true)
+                .with_deferred(flags.is_none()),
+        ))
+    }
+
+    fn extract_multi(carrier: &dyn Extractor) -> Result<Option<SpanContextState>> {
+        let trace_id = if let Some(value) = carrier.get(TRACE_ID) {
+            track!(parse_trace_id(value.trim()))?
+        } else {
+            return Ok(None);
+        };
+        let span_id = track_assert_some!(carrier.get(SPAN_ID), ErrorKind::MalformedHeader);
+        let span_id = track!(parse_span_id(span_id.trim()))?;
+        let parent_span_id = if let Some(value) = carrier.get(PARENT_SPAN_ID) {
+            track!(parse_span_id(value.trim()))?
+        } else {
+            0
+        };
+        let mut flags = match carrier.get(SAMPLED).map(|v| v.trim()) {
+            None => None,
+            Some("0") | Some("false") => Some(0),
+            Some("1") | Some("true") => Some(FLAG_SAMPLED),
+            Some(value) => track_panic!(ErrorKind::MalformedHeader; value),
+        };
+        match carrier.get(FLAGS).map(|v| v.trim()) {
+            None | Some("0") => {}
+            Some("1") => flags = Some(FLAG_SAMPLED | FLAG_DEBUG),
+            Some(value) => track_panic!(ErrorKind::MalformedHeader; value),
+        }
+        Ok(Some(
+            SpanContextState::new(trace_id, span_id)
+                .with_parent_span_id(parent_span_id)
+                .with_flags(flags.unwrap_or(0))
+                .with_remote(true)
+                .with_deferred(flags.is_none()),
+        ))
+    }
+}
+impl Propagator for B3Propagator {
+    fn inject(
+        &self,
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut dyn Injector,
+    ) -> Result<()> {
+        let state = context.state();
+        track_assert!(state.is_valid(), ErrorKind::InvalidInput; state);
+        let trace_id = format_trace_id(state.trace_id());
+        let span_id = format!("{:016x}", state.span_id());
+        let sampled = if state.is_debug() {
+            Some("d")
+        } else if state.is_sampled() {
+            Some("1")
+        } else if state.is_deferred() {
+            None
+        } else {
+            Some("0")
+        };
+        if self.single_header {
+            let mut value = format!("{}-{}", trace_id, span_id);
+            if let Some(sampled) = sampled {
+                value.push('-');
+                value.push_str(sampled);
+                if let Some(parent_span_id) = state.parent_span_id() {
+                    value.push_str(&format!("-{:016x}", parent_span_id));
+                }
+            }
+            track!(carrier.set(B3, &value))?;
+        } else {
+            track!(carrier.set(TRACE_ID, &trace_id))?;
+            track!(carrier.set(SPAN_ID, &span_id))?;
+            if let Some(parent_span_id) = state.parent_span_id() {
+                track!(carrier.set(PARENT_SPAN_ID, &format!("{:016x}", parent_span_id)))?;
+            }
+            match sampled {
+                Some("d") => track!(carrier.set(FLAGS, "1"))?,
+                Some(sampled) => track!(carrier.set(SAMPLED, sampled))?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn extract(&self, carrier: &dyn Extractor) -> Result<Option<SpanContext<SpanContextState>>> {
+        let state = if let Some(value) = carrier.get(B3) {
+            track!(Self::extract_single(value))?
+        } else {
+            track!(Self::extract_multi(carrier))?
+        };
+        Ok(state.map(|state| {
+            let is_debug = state.is_debug();
+            let mut context = SpanContext::new(state, Vec::new());
+            if is_debug {
+                context.set_sampling_priority(1);
+            }
+            context
+        }))
+    }
+}
+
+/// 64-bit trace identifiers are formatted as 16 hexadecimal characters for interoperability.
+fn format_trace_id(trace_id: TraceId) -> String {
+    if trace_id.high == 0 {
+        format!("{:016x}", trace_id.low)
+    } else {
+        trace_id.to_string()
+    }
+}
+
+fn parse_trace_id(s: &str) -> Result<TraceId> {
+    track_assert!(s.len() == 16 || s.len() == 32, ErrorKind::MalformedHeader; s);
+    let trace_id = s
+        .parse::<TraceId>()
+        .map_err(|e| track!(Error::from(ErrorKind::MalformedHeader.takes_over(e))))?;
+    track_assert!(trace_id.is_valid(), ErrorKind::MalformedHeader; s);
+    Ok(trace_id)
+}
+
+fn parse_span_id(s: &str) -> Result<u64> {
+    track_assert_eq!(s.len(), 16, ErrorKind::MalformedHeader; s);
+    track_assert!(s.bytes().all(|b| b.is_ascii_hexdigit()), ErrorKind::MalformedHeader; s);
+    let span_id = u64::from_str_radix(s, 16)
+        .map_err(|e| track!(Error::from(ErrorKind::MalformedHeader.cause(e))))?;
+    track_assert_ne!(span_id, 0, ErrorKind::MalformedHeader; s);
+    Ok(span_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::{AllSampler, NullSampler, ParentBasedSampler};
+    use crate::Tracer;
+    use std::collections::{BTreeMap, HashMap};
+
+    fn extract(fields: &[(&str, &str)]) -> Result<Option<SpanContext<SpanContextState>>> {
+        let carrier = fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        B3Propagator::new().extract_from_http_header(&carrier)
+    }
+
+    #[test]
+    fn extract_multi_works() {
+        let context = extract(&[
+            ("X-B3-TraceId", "463ac35c9f6413ad48485a3953bb6124"),
+            ("X-B3-SpanId", "a2fb4a1d1a96d312"),
+            ("X-B3-ParentSpanId", "0020000000000001"),
+            ("X-B3-Sampled", "1"),
+        ])
+        .unwrap()
+        .unwrap();
+        let state = context.state();
+        assert_eq!(
+            state.trace_id().to_string(),
+            "463ac35c9f6413ad48485a3953bb6124"
+        );
+        assert_eq!(state.span_id(), 0xa2fb_4a1d_1a96_d312);
+        assert_eq!(state.parent_span_id(), Some(0x0020_0000_0000_0001));
+        assert!(state.is_sampled());
+        assert!(!state.is_debug());
+        assert!(!state.is_deferred());
+        assert_eq!(context.sampling_priority(), None);
+
+        let context = extract(&[
+            ("X-B3-TraceId", "48485a3953bb6124"),
+            ("X-B3-SpanId", "a2fb4a1d1a96d312"),
+            ("X-B3-Flags", "1"),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(context.state().trace_id().high, 0);
+        assert!(context.state().is_debug());
+        assert!(!context.state().is_deferred());
+        assert_eq!(context.sampling_priority(), Some(1));
+
+        assert!(extract(&[("X-B3-Sampled", "0")]).unwrap().is_none());
+        assert!(extract(&[("X-B3-TraceId", "48485a3953bb6124")]).is_err());
+        assert!(extract(&[
+            ("X-B3-TraceId", "48485a3953bb6124"),
+            ("X-B3-SpanId", "a2fb4a1d1a96d312"),
+            ("X-B3-Sampled", "yes"),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn extract_single_works() {
+        let context = extract(&[(
+            "b3",
+            "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1-05e3ac9a4f6e3b90",
+        )])
+        .unwrap()
+        .unwrap();
+        let state = context.state();
+        assert_eq!(state.span_id(), 0xe457_b5a2_e4d8_6bd1);
+        assert_eq!(state.parent_span_id(), Some(0x05e3_ac9a_4f6e_3b90));
+        assert!(state.is_sampled());
+
+        let context = extract(&[("b3", "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1")])
+            .unwrap()
+            .unwrap();
+        assert!(!context.state().is_sampled());
+        assert!(context.state().is_deferred());
+
+        assert!(extract(&[("b3", "d")]).unwrap().is_none());
+        for value in [
+            "x",
+            "80f198ee56343ba864fe8b2a57d3eff7",
+            "80f198ee56343ba8-e457b5a2e4d86bd1-2",
+            "80f198ee56343ba-e457b5a2e4d86bd1-1",
+            "0000000000000000-e457b5a2e4d86bd1-1",
+            "80f198ee56343ba8-e457b5a2e4d86bd1-1-05e3ac9a4f6e3b90-1",
+        ] {
+            let e = extract(&[("b3", value)]).err().unwrap();
+            assert_eq!(*e.kind(), ErrorKind::MalformedHeader, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn extract_without_sampling_state_works() {
+        let context = extract(&[
+            ("X-B3-TraceId", "463ac35c9f6413ad48485a3953bb6124"),
+            ("X-B3-SpanId", "a2fb4a1d1a96d312"),
+        ])
+        .unwrap()
+        .unwrap();
+        assert!(context.state().is_deferred());
+        assert!(!context.state().is_sampled());
+        assert_eq!(context.sampling_priority(), None);
+
+        let is_sampled = |context: &SpanContext<SpanContextState>,
+                          sampler: ParentBasedSampler<_>| {
+            let (tracer, _span_rx) = Tracer::new(sampler);
+            let span = tracer.span("foo").child_of(context).start();
+            assert!(span.context().is_none_or(|c| !c.state().is_deferred()));
+            span.is_sampled()
+        };
+        assert!(is_sampled(&context, ParentBasedSampler::new(AllSampler)));
+        assert!(!is_sampled(&context, ParentBasedSampler::new(NullSampler)));
+
+        let context = extract(&[
+            ("X-B3-TraceId", "463ac35c9f6413ad48485a3953bb6124"),
+            ("X-B3-SpanId", "a2fb4a1d1a96d312"),
+            ("X-B3-Sampled", "0"),
+        ])
+        .unwrap()
+        .unwrap();
+        assert!(!context.state().is_deferred());
+        assert!(!is_sampled(&context, ParentBasedSampler::new(AllSampler)));
+
+        // The deferred state is propagated as is.
+        let state = SpanContextState::new(TraceId { high: 0, low: 1 }, 2)
+            .with_parent_span_id(3)
+            .with_deferred(true);
+        let context = SpanContext::new(state, Vec::new());
+        let mut carrier = BTreeMap::new();
+        B3Propagator::new()
+            .inject_to_text_map(&context, &mut carrier)
+            .unwrap();
+        assert!(!carrier.contains_key(SAMPLED));
+        assert!(!carrier.contains_key(FLAGS));
+
+        let propagator = B3Propagator::new().single_header(true);
+        let mut carrier = HashMap::new();
+        propagator
+            .inject_to_text_map(&context, &mut carrier)
+            .unwrap();
+        assert_eq!(
+            carrier.get("b3").map(|v| v.as_str()),
+            Some("0000000000000001-0000000000000002")
+        );
+        let extracted = propagator.extract_from_text_map(&carrier).unwrap().unwrap();
+        assert!(extracted.state().is_deferred());
+    }
+
+    #[test]
+    fn inject_works() {
+        let state = SpanContextState::new(TraceId { high: 0, low: 1 }, 2)
+            .with_parent_span_id(3)
+            .with_flags(FLAG_SAMPLED);
+        let context = SpanContext::new(state, Vec::new());
+
+        let mut carrier = BTreeMap::new();
+        B3Propagator::new()
+            .inject_to_text_map(&context, &mut carrier)
+            .unwrap();
+        assert_eq!(
+            carrier.into_iter().collect::<Vec<_>>(),
+            [
+                ("x-b3-parentspanid", "0000000000000003"),
+                ("x-b3-sampled", "1"),
+                ("x-b3-spanid", "0000000000000002"),
+                ("x-b3-traceid", "0000000000000001"),
+            ]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+        );
+
+        let propagator = B3Propagator::new().single_header(true);
+        let mut carrier = HashMap::new();
+        propagator
+            .inject_to_text_map(&context, &mut carrier)
+            .unwrap();
+        assert_eq!(
+            carrier.get("b3").map(|v| v.as_str()),
+            Some("0000000000000001-0000000000000002-1-0000000000000003")
+        );
+        let extracted = propagator.extract_from_text_map(&carrier).unwrap().unwrap();
+        assert_eq!(
+            extracted.state(),
+            &context.state().clone().with_remote(true)
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use super::{percent_decode, percent_encode_if, ExtractResult, Extractor, Injector, Propagator};
+use crate::span::{BaggageItem, BaggageProperty, SpanContext};
+use crate::state::SpanContextState;
+use crate::{ErrorKind, Result};
+
+const BAGGAGE: &str = "baggage";
+
+/// The maximum number of the list-members in a `baggage` field.
+pub const MAX_BAGGAGE_MEMBERS: usize = 180;
+
+/// The maximum byte length of a list-member in a `baggage` field.
+pub const MAX_BAGGAGE_MEMBER_BYTES: usize = 4096;
+
+/// The maximum byte length of a `baggage` field.
+pub const MAX_BAGGAGE_BYTES: usize = 8192;
+
+/// [W3C Baggage][baggage] propagator.
+///
+/// This injects the baggage items of a span context to the `baggage` field
+/// and extracts them from the field.
+/// Since the field carries no span identifiers,
+/// `extract` always returns `Ok(None)` and the items are extracted by `extract_baggage`.
+///
+/// The names of the items and their properties must be tokens (cf. RFC 7230),
+/// and the values are percent-encoded.
+/// When injecting, the items which violate the syntax or which exceed the limits
+/// (i.e., `MAX_BAGGAGE_MEMBERS`, `MAX_BAGGAGE_MEMBER_BYTES` and `MAX_BAGGAGE_BYTES`) are not injected.
+/// When extracting, such items make the field malformed.
+///
+/// [baggage]: https://www.w3.org/TR/baggage/
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::propagation::BaggagePropagator;
+/// use rustracing::span::{BaggageItem, BaggageProperty};
+///
+/// let items = vec![
+///     BaggageItem::new("user", "foo bar")
+///         .with_properties(vec![BaggageProperty::new("ttl", Some("60"))]),
+///     BaggageItem::new("tenant", "a,b"),
+/// ];
+/// let value = BaggagePropagator::encode(&items);
+/// assert_eq!(value, "user=foo%20bar;ttl=60,tenant=a%2Cb");
+///
+/// let decoded = BaggagePropagator::decode(&value).unwrap();
+/// assert_eq!(decoded[0].value(), "foo bar");
+/// assert_eq!(decoded[0].properties()[0].value(), Some("60"));
+/// assert_eq!(decoded[1].value(), "a,b");
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct BaggagePropagator;
+impl BaggagePropagator {
+    /// Encodes `items` as a `baggage` field value.
+    pub fn encode(items: &[BaggageItem]) -> String {
+        let mut encoded = String::new();
+        let mut members = 0;
+        for item in items {
+            if members == MAX_BAGGAGE_MEMBERS {
+                break;
+            }
+            let member = if let Some(member) = encode_member(item) {
+                member
+            } else {
+                continue;
+            };
+            let len = if members == 0 {
+                member.len()
+            } else {
+                member.len() + 1
+            };
+            if member.len() > MAX_BAGGAGE_MEMBER_BYTES || encoded.len() + len > MAX_BAGGAGE_BYTES {
+                continue;
+            }
+            if members != 0 {
+                encoded.push(',');
+            }
+            encoded.push_str(&member);
+            members += 1;
+        }
+        encoded
+    }
+
+    /// Decodes a `baggage` field value.
+    ///
+    /// # Errors
+    ///
+    /// If `value` is malformed or exceeds the limits,
+    /// it will return an error with the kind `ErrorKind::MalformedHeader`.
+    pub fn decode(value: &str) -> Result<Vec<BaggageItem>> {
+        track_assert!(
+            value.len() <= MAX_BAGGAGE_BYTES,
+            ErrorKind::MalformedHeader,
+            "Too large baggage: {} bytes",
+            value.len()
+        );
+        let mut items = Vec::new();
+        for member in value.split(',') {
+            let member = trim_ows(member);
+            if member.is_empty() {
+                continue;
+            }
+            track_assert!(member.len() <= MAX_BAGGAGE_MEMBER_BYTES, ErrorKind::MalformedHeader; member);
+            items.push(track!(decode_member(member))?);
+        }
+        track_assert!(
+            items.len() <= MAX_BAGGAGE_MEMBERS,
+            ErrorKind::MalformedHeader,
+            "Too many baggage members: {}",
+            items.len()
+        );
+        Ok(items)
+    }
+}
+impl Propagator for BaggagePropagator {
+    fn inject(
+        &self,
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut dyn Injector,
+    ) -> Result<()> {
+        let value = Self::encode(context.baggage_items());
+        if !value.is_empty() {
+            track!(carrier.set(BAGGAGE, &value))?;
+        }
+        Ok(())
+    }
+
+    fn extract(&self, _carrier: &dyn Extractor) -> Result<Option<SpanContext<SpanContextState>>> {
+        Ok(None)
+    }
+
+    fn extract_baggage(&self, carrier: &dyn Extractor) -> Result<Vec<BaggageItem>> {
+        if let Some(value) = carrier.get(BAGGAGE) {
+            track!(Self::decode(value))
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    fn extract_with_baggage(&self, carrier: &dyn Extractor) -> ExtractResult {
+        (Ok(None), track!(self.extract_baggage(carrier)))
+    }
+}
+
+fn encode_member(item: &BaggageItem) -> Option<String> {
+    if !is_token(item.name()) {
+        return None;
+    }
+    let mut member = format!("{}={}", item.name(), encode_value(item.value()));
+    for property in item.properties() {
+        if !is_token(property.name()) {
+            return None;
+        }
+        member.push(';');
+        member.push_str(property.name());
+        if let Some(value) = property.value() {
+            member.push('=');
+            member.push_str(&encode_value(value));
+        }
+    }
+    Some(member)
+}
+
+fn decode_member(member: &str) -> Result<BaggageItem> {
+    let mut parts = member.split(';');
+    let (name, value) = track!(decode_key_value(parts.next().unwrap_or("")))?;
+    let value = track_assert_some!(value, ErrorKind::MalformedHeader; member);
+    let mut properties = Vec::new();
+    for property in parts {
+        let (name, value) = track!(decode_key_value(property))?;
+        properties.push(BaggageProperty::new(name, value.as_deref()));
+    }
+    Ok(BaggageItem::new(name, &value).with_properties(properties))
+}
+
+fn decode_key_value(s: &str) -> Result<(&str, Option<String>)> {
+    let mut kv = s.splitn(2, '=');
+    let key = trim_ows(kv.next().unwrap_or(""));
+    track_assert!(is_token(key), ErrorKind::MalformedHeader; s);
+    if let Some(value) = kv.next().map(trim_ows) {
+        track_assert!(
+            value.bytes().all(is_baggage_octet),
+            ErrorKind::MalformedHeader; s
+        );
+        let value = track!(percent_decode(value, false))?;
+        Ok((key, Some(value)))
+    } else {
+        Ok((key, None))
+    }
+}
+
+fn encode_value(value: &str) -> String {
+    percent_encode_if(value, |b| b == b'%' || !is_baggage_octet(b))
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes().all(|b| {
+            b.is_ascii_alphanumeric()
+                || matches!(
+                    b,
+                    b'!' | b'#'
+                        | b'$'
+                        | b'%'
+                        | b'&'
+                        | b'\''
+                        | b'*'
+                        | b'+'
+                        | b'-'
+                        | b'.'
+                        | b'^'
+                        | b'_'
+                        | b'`'
+                        | b'|'
+                        | b'~'
+                )
+        })
+}
+
+fn is_baggage_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e)
+}
+
+fn trim_ows(s: &str) -> &str {
+    s.trim_matches(|c| c == ' ' || c == '\t')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::TraceId;
+    use std::collections::HashMap;
+
+    #[test]
+    fn decode_works() {
+        let items =
+            BaggagePropagator::decode(" a = 1 ;p1; p2 = x%3By ,, b=%E3%81%82+ ,c=").unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].name(), "a");
+        assert_eq!(items[0].value(), "1");
+        assert_eq!(
+            items[0].properties(),
+            &[
+                BaggageProperty::new("p1", None),
+                BaggageProperty::new("p2", Some("x;y"))
+            ]
+        );
+        assert_eq!(items[1].value(), "\u{3042}+");
+        assert_eq!(items[2].value(), "");
+
+        for value in ["a", "=1", "a b=1", "a=1 2", "a=\"1\"", "a=%zz", "a=1;=2"] {
+            let e = BaggagePropagator::decode(value).err().unwrap();
+            assert_eq!(*e.kind(), ErrorKind::MalformedHeader, "{:?}", value);
+        }
+
+        let too_many = (0..=MAX_BAGGAGE_MEMBERS)
+            .map(|i| format!("k{}=v", i))
+            .collect::<Vec<_>>();
+        assert!(BaggagePropagator::decode(&too_many.join(",")).is_err());
+
+        let too_large = format!("k={}", "v".repeat(MAX_BAGGAGE_MEMBER_BYTES));
+        assert!(BaggagePropagator::decode(&too_large).is_err());
+    }
+
+    #[test]
+    fn encode_works() {
+        let items = vec![
+            BaggageItem::new("a", "x %\u{3042}"),
+            BaggageItem::new("invalid name", "v"),
+            BaggageItem::new("b", &"v".repeat(MAX_BAGGAGE_MEMBER_BYTES)),
+            BaggageItem::new("c", "1").with_properties(vec![BaggageProperty::new("p", None)]),
+        ];
+        assert_eq!(
+            BaggagePropagator::encode(&items),
+            "a=x%20%25%E3%81%82,c=1;p"
+        );
+
+        let items = (0..=MAX_BAGGAGE_MEMBERS)
+            .map(|i| BaggageItem::new(&format!("k{}", i), "v"))
+            .collect::<Vec<_>>();
+        let encoded = BaggagePropagator::encode(&items);
+        assert_eq!(encoded.split(',').count(), MAX_BAGGAGE_MEMBERS);
+    }
+
+    #[test]
+    fn merge_into_span_context_works() {
+        let state = SpanContextState::new(TraceId { high: 0, low: 1 }, 2);
+        let context = SpanContext::new(state, vec![BaggageItem::new("a", "1")]);
+
+        let mut carrier = HashMap::new();
+        context.inject_to_http_header(&mut carrier).unwrap();
+        assert_eq!(carrier.get("baggage").map(|v| v.as_str()), Some("a=1"));
+
+        carrier.insert("baggage".to_owned(), "a=2,b=3".to_owned());
+        let extracted = SpanContext::<SpanContextState>::extract_from_http_header(&carrier)
+            .unwrap()
+            .unwrap();
+        let items = extracted
+            .baggage_items()
+            .iter()
+            .map(|x| (x.name(), x.value()))
+            .collect::<Vec<_>>();
+        assert_eq!(items, [("a", "2"), ("b", "3")]);
+    }
+
+    #[test]
+    fn malformed_baggage_does_not_prevent_extraction() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let too_large = format!("k={}", "v".repeat(MAX_BAGGAGE_BYTES));
+        for baggage in ["a=1,invalid", too_large.as_str()] {
+            let mut carrier = HashMap::new();
+            carrier.insert("traceparent".to_owned(), traceparent.to_owned());
+            carrier.insert("baggage".to_owned(), baggage.to_owned());
+            let extracted = SpanContext::<SpanContextState>::extract_from_http_header(&carrier)
+                .unwrap()
+                .unwrap();
+            assert_eq!(extracted.state().span_id(), 0x00f0_67aa_0ba9_02b7);
+            assert!(extracted.baggage_items().is_empty());
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use crate::carrier::{ExtractFromBinary, InjectToBinary};
+use crate::span::{BaggageItem, BaggageProperty, SpanContext};
+use crate::state::{SpanContextState, TraceId, TraceState};
+use crate::{Error, ErrorKind, Result};
+use std::io::{Read, Write};
+use trackable::error::ErrorKindExt;
+
+const VERSION: u8 = 0;
+const MAX_BODY_LEN: usize = 64 * 1024;
+
+const FIELD_TRACE_ID: u8 = 0;
+const FIELD_SPAN_ID: u8 = 1;
+const FIELD_PARENT_SPAN_ID: u8 = 2;
+const FIELD_FLAGS: u8 = 3;
+const FIELD_TRACE_STATE: u8 = 4;
+const FIELD_BAGGAGE_ITEM: u8 = 5;
+const FIELD_BAGGAGE_PROPERTY: u8 = 6;
+
+impl<W: Write> InjectToBinary<W> for SpanContextState {
+    fn inject_to_binary(context: &SpanContext<Self>, carrier: &mut W) -> Result<()> {
+        let state = context.state();
+        track_assert!(state.is_valid(), ErrorKind::InvalidInput; state);
+
+        let mut body = Vec::new();
+        let trace_id = state.trace_id().to_u128().to_be_bytes();
+        track!(put_field(&mut body, FIELD_TRACE_ID, &trace_id))?;
+        track!(put_field(
+            &mut body,
+            FIELD_SPAN_ID,
+            &state.span_id().to_be_bytes()
+        ))?;
+        if let Some(parent_span_id) = state.parent_span_id() {
+            let parent_span_id = parent_span_id.to_be_bytes();
+            track!(put_field(&mut body, FIELD_PARENT_SPAN_ID, &parent_span_id))?;
+        }
+        track!(put_field(&mut body, FIELD_FLAGS, &[state.flags()]))?;
+        if !state.trace_state().is_empty() {
+            let trace_state = state.trace_state().to_string();
+            track!(put_field(
+                &mut body,
+                FIELD_TRACE_STATE,
+                trace_state.as_bytes()
+            ))?;
+        }
+        for item in context.baggage_items() {
+            let mut payload = Vec::new();
+            track!(put_bytes(&mut payload, item.name().as_bytes()))?;
+            payload.extend_from_slice(item.value().as_bytes());
+            track!(put_field(&mut body, FIELD_BAGGAGE_ITEM, &payload))?;
+            for property in item.properties() {
+                let mut payload = Vec::new();
+                track!(put_bytes(&mut payload, property.name().as_bytes()))?;
+                if let Some(value) = property.value() {
+                    payload.push(1);
+                    payload.extend_from_slice(value.as_bytes());
+                } else {
+                    payload.push(0);
+                }
+                track!(put_field(&mut body, FIELD_BAGGAGE_PROPERTY, &payload))?;
+            }
+        }
+        track_assert!(body.len() <= MAX_BODY_LEN, ErrorKind::InvalidInput; body.len());
+
+        let mut buf = Vec::with_capacity(5 + body.len());
+        buf.push(VERSION);
+        buf.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&body);
+        carrier
+            .write_all(&buf)
+            .map_err(|e| track!(Error::from(ErrorKind::Other.cause(e))))
+    }
+}
+impl<R: Read> ExtractFromBinary<R> for SpanContextState {
+    fn extract_from_binary(carrier: &mut R) -> Result<Option<SpanContext<Self>>> {
+        let mut header = [0; 5];
+        let n = track!(read_full(carrier, &mut header))?;
+        if n == 0 {
+            return Ok(None);
+        }
+        track_assert_eq!(n, header.len(), ErrorKind::InvalidInput, "Unexpected EOF");
+        track_assert_eq!(
+            header[0],
+            VERSION,
+            ErrorKind::InvalidInput,
+            "Unsupported version"
+        );
+        let body_len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        track_assert!(body_len <= MAX_BODY_LEN, ErrorKind::InvalidInput; body_len);
+
+        let mut body = vec![0; body_len];
+        let n = track!(read_full(carrier, &mut body))?;
+        track_assert_eq!(n, body_len, ErrorKind::InvalidInput, "Unexpected EOF");
+
+        let mut trace_id = None;
+        let mut span_id = None;
+        let mut parent_span_id = 0;
+        let mut flags = 0;
+        let mut trace_state = TraceState::new();
+        let mut baggage_items = Vec::new();
+        let mut rest = &body[..];
+        while let Some((&field, tail)) = rest.split_first() {
+            rest = tail;
+            let payload = track!(take_bytes(&mut rest))?;
+            match field {
+                FIELD_TRACE_ID => {
+                    let bytes = track!(fixed::<16>(payload))?;
+                    trace_id = Some(TraceId::from(u128::from_be_bytes(bytes)));
+                }
+                FIELD_SPAN_ID => span_id = Some(u64::from_be_bytes(track!(fixed::<8>(payload))?)),
+                FIELD_PARENT_SPAN_ID => {
+                    parent_span_id = u64::from_be_bytes(track!(fixed::<8>(payload))?)
+                }
+                FIELD_FLAGS => flags = track!(fixed::<1>(payload))?[0],
+                FIELD_TRACE_STATE => {
+                    trace_state = track!(utf8(payload))?
+                        .parse::<TraceState>()
+                        .map_err(|e| track!(Error::from(ErrorKind::InvalidInput.takes_over(e))))?;
+                }
+                FIELD_BAGGAGE_ITEM => {
+                    let mut payload = payload;
+                    let name = track!(utf8(track!(take_bytes(&mut payload))?))?;
+                    let value = track!(utf8(payload))?;
+                    baggage_items.push((BaggageItem::new(name, value), Vec::new()));
+                }
+                FIELD_BAGGAGE_PROPERTY => {
+                    let mut payload = payload;
+                    let name = track!(utf8(track!(take_bytes(&mut payload))?))?;
+                    let (&has_value, value) = track_assert_some!(
+                        payload.split_first(),
+                        ErrorKind::InvalidInput,
+                        "Truncated field"
+                    );
+                    let value = match has_value {
+                        0 => {
+                            track_assert!(value.is_empty(), ErrorKind::InvalidInput);
+                            None
+                        }
+                        1 => Some(track!(utf8(value))?),
+                        _ => track_panic!(ErrorKind::InvalidInput; has_value),
+                    };
+                    let (_, properties) = track_assert_some!(
+                        baggage_items.last_mut(),
+                        ErrorKind::InvalidInput,
+                        "No baggage item for the property"
+                    );
+                    properties.push(BaggageProperty::new(name, value));
+                }
+                _ => {
+                    // Unknown fields are skipped for forward compatibility.
+                }
+            }
+        }
+
+        let trace_id = track_assert_some!(trace_id, ErrorKind::InvalidInput, "No trace ID");
+        let span_id = track_assert_some!(span_id, ErrorKind::InvalidInput, "No span ID");
+        let state = SpanContextState::new(trace_id, span_id)
+            .with_parent_span_id(parent_span_id)
+            .with_flags(flags)
+            .with_trace_state(trace_state)
+            .with_remote(true);
+        track_assert!(state.is_valid(), ErrorKind::InvalidInput; state);
+
+        let baggage_items = baggage_items
+            .into_iter()
+            .map(|(item, properties)| item.with_properties(properties))
+            .collect();
+        let is_debug = state.is_debug();
+        let mut context = SpanContext::new(state, baggage_items);
+        if is_debug {
+            context.set_sampling_priority(1);
+        }
+        Ok(Some(context))
+    }
+}
+
+fn put_field(buf: &mut Vec<u8>, field: u8, payload: &[u8]) -> Result<()> {
+    buf.push(field);
+    track!(put_bytes(buf, payload))
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) -> Result<()> {
+    track_assert!(bytes.len() <= 0xFFFF, ErrorKind::InvalidInput; bytes.len());
+    buf.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    buf.extend_from_slice(bytes);
+    Ok(())
+}
+
+fn take_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8]> {
+    track_assert!(buf.len() >= 2, ErrorKind::InvalidInput, "Truncated field");
+    let len = usize::from(u16::from_be_bytes([buf[0], buf[1]]));
+    track_assert!(
+        buf.len() >= 2 + len,
+        ErrorKind::InvalidInput,
+        "Truncated field"
+    );
+    let bytes = &buf[2..2 + len];
+    *buf = &buf[2 + len..];
+    Ok(bytes)
+}
+
+fn fixed<const N: usize>(payload: &[u8]) -> Result<[u8; N]> {
+    track_assert_eq!(payload.len(), N, ErrorKind::InvalidInput);
+    let mut bytes = [0; N];
+    bytes.copy_from_slice(payload);
+    Ok(bytes)
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str> {
+    std::str::from_utf8(bytes).map_err(|e| track!(Error::from(ErrorKind::InvalidInput.cause(e))))
+}
+
+/// Reads bytes until `buf` is filled or EOF is reached, and returns the number of the read bytes.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut offset = 0;
+    while offset < buf.len() {
+        match reader.read(&mut buf[offset..]) {
+            Ok(0) => break,
+            Ok(n) => offset += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(track!(Error::from(ErrorKind::Other.cause(e)))),
+        }
+    }
+    Ok(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{FLAG_DEBUG, FLAG_SAMPLED};
+
+    fn context() -> SpanContext<SpanContextState> {
+        let mut trace_state = TraceState::new();
+        trace_state.insert("vendor", "x").unwrap();
+        let state = SpanContextState::new(TraceId { high: 1, low: 2 }, 3)
+            .with_parent_span_id(4)
+            .with_flags(FLAG_SAMPLED | FLAG_DEBUG)
+            .with_trace_state(trace_state);
+        let items = vec![
+            BaggageItem::new("user", "foo"),
+            BaggageItem::new("tenant", "bar").with_properties(vec![
+                BaggageProperty::new("ttl", Some("60")),
+                BaggageProperty::new("private", None),
+            ]),
+        ];
+        SpanContext::new(state, items)
+    }
+
+    #[test]
+    fn round_trip_works() {
+        let context = context();
+        let mut buf = Vec::new();
+        context.inject_to_binary(&mut buf).unwrap();
+        buf.extend_from_slice(b"trailing data");
+
+        let mut reader = &buf[..];
+        let extracted = SpanContext::<SpanContextState>::extract_from_binary(&mut reader)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            extracted.state(),
+            &context.state().clone().with_remote(true)
+        );
+        let items = extracted
+            .baggage_items()
+            .iter()
+            .map(|x| (x.name(), x.value(), x.properties()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            context
+                .baggage_items()
+                .iter()
+                .map(|x| (x.name(), x.value(), x.properties()))
+                .collect::<Vec<_>>()
+        );
+        assert!(items.iter().any(|x| x.0 == "tenant" && x.2.len() == 2));
+        assert_eq!(extracted.sampling_priority(), Some(1));
+        assert_eq!(reader, b"trailing data");
+
+        let mut empty: &[u8] = &[];
+        assert!(
+            SpanContext::<SpanContextState>::extract_from_binary(&mut empty)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_skipped() {
+        let mut buf = Vec::new();
+        context().inject_to_binary(&mut buf).unwrap();
+
+        let unknown = [0xFF, 0x00, 0x03, b'a', b'b', b'c'];
+        let body_len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) + unknown.len() as u32;
+        buf[1..5].copy_from_slice(&body_len.to_be_bytes());
+        buf.extend_from_slice(&unknown);
+
+        let extracted = SpanContext::<SpanContextState>::extract_from_binary(&mut &buf[..])
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            extracted.state(),
+            &context().state().clone().with_remote(true)
+        );
+    }
+
+    #[test]
+    fn malformed_input_fails() {
+        let mut buf = Vec::new();
+        context().inject_to_binary(&mut buf).unwrap();
+
+        let mut truncated = &buf[..buf.len() - 1];
+        assert!(SpanContext::<SpanContextState>::extract_from_binary(&mut truncated).is_err());
+
+        let mut unknown_version = buf.clone();
+        unknown_version[0] = 1;
+        assert!(
+            SpanContext::<SpanContextState>::extract_from_binary(&mut &unknown_version[..])
+                .is_err()
+        );
+
+        let no_ids = [VERSION, 0, 0, 0, 4, FIELD_FLAGS, 0, 1, 1];
+        assert!(SpanContext::<SpanContextState>::extract_from_binary(&mut &no_ids[..]).is_err());
+    }
+}
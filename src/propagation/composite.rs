@@ -0,0 +1,306 @@
+use super::{BoxPropagator, ExtractResult, Extractor, Injector, Propagator};
+use crate::span::{BaggageItem, SpanContext};
+use crate::state::SpanContextState;
+use crate::{Error, Result};
+use std::fmt;
+
+/// A propagator which combines multiple propagators.
+///
+/// On injection, every propagator injects the context in its format.
+///
+/// On extraction, the propagators are tried in order and the first extracted context is returned.
+/// Errors of the propagators are ignored if one of the subsequent propagators succeeds in extraction.
+/// If `merge_baggage(true)` is specified, the baggage items extracted by all the propagators are
+/// merged into the context (the items of the earlier propagators take precedence).
+/// Errors on extracting baggage items are ignored in that case,
+/// so that a malformed baggage field does not discard the valid context.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::propagation::{
+///     B3Propagator, BaggagePropagator, CompositePropagator, JaegerPropagator, Propagator,
+///     TraceContextPropagator,
+/// };
+/// use std::collections::HashMap;
+///
+/// let propagator = CompositePropagator::new()
+///     .propagator(TraceContextPropagator)
+///     .propagator(B3Propagator::new())
+///     .propagator(JaegerPropagator)
+///     .propagator(BaggagePropagator)
+///     .merge_baggage(true);
+///
+/// let mut headers = HashMap::new();
+/// headers.insert("uber-trace-id".to_owned(), "1:2:0:1".to_owned());
+/// headers.insert("baggage".to_owned(), "user=foo".to_owned());
+///
+/// let context = propagator.extract_from_http_header(&headers).unwrap().unwrap();
+/// assert_eq!(context.state().span_id(), 2);
+/// assert_eq!(context.baggage_items()[0].value(), "foo");
+/// ```
+#[derive(Default)]
+pub struct CompositePropagator {
+    propagators: Vec<BoxPropagator>,
+    merge_baggage: bool,
+}
+impl CompositePropagator {
+    /// Makes a new `CompositePropagator` instance which has no propagators.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `propagator` to the end of the propagator list.
+    pub fn propagator<P>(mut self, propagator: P) -> Self
+    where
+        P: Propagator + Send + Sync + 'static,
+    {
+        self.propagators.push(propagator.boxed());
+        self
+    }
+
+    /// Sets whether to merge the baggage items extracted by all the propagators.
+    ///
+    /// The default value is `false`.
+    pub fn merge_baggage(mut self, enabled: bool) -> Self {
+        self.merge_baggage = enabled;
+        self
+    }
+
+    /// Extracts the first span context and the baggage items of all the propagators
+    /// (the items of the earlier propagators take precedence).
+    ///
+    /// Each propagator parses `carrier` only once.
+    /// The baggage items of the propagators which fail are skipped and the first error is returned.
+    fn extract_all(
+        &self,
+        carrier: &dyn Extractor,
+    ) -> (
+        Result<Option<SpanContext<SpanContextState>>>,
+        Vec<BaggageItem>,
+        Option<Error>,
+    ) {
+        let mut context = None;
+        let mut error = None;
+        let mut items: Vec<BaggageItem> = Vec::new();
+        let mut baggage_error = None;
+        for propagator in &self.propagators {
+            let (c, i) = propagator.extract_with_baggage(carrier);
+            match track!(c) {
+                Ok(c) => {
+                    if context.is_none() {
+                        context = c;
+                    }
+                }
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+            match track!(i) {
+                Ok(i) => {
+                    for item in i {
+                        if items.iter().all(|x| x.name() != item.name()) {
+                            items.push(item);
+                        }
+                    }
+                }
+                Err(e) => {
+                    baggage_error.get_or_insert(e);
+                }
+            }
+        }
+        let context = match (context, error) {
+            (Some(context), _) => Ok(Some(context)),
+            (None, Some(e)) => Err(e),
+            (None, None) => Ok(None),
+        };
+        (context, items, baggage_error)
+    }
+}
+impl Propagator for CompositePropagator {
+    fn inject(
+        &self,
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut dyn Injector,
+    ) -> Result<()> {
+        for propagator in &self.propagators {
+            track!(propagator.inject(context, carrier))?;
+        }
+        Ok(())
+    }
+
+    fn extract(&self, carrier: &dyn Extractor) -> Result<Option<SpanContext<SpanContextState>>> {
+        if self.merge_baggage {
+            let (context, items, _) = self.extract_all(carrier);
+            let mut context = track!(context)?;
+            if let Some(context) = context.as_mut() {
+                context.merge_baggage_items(items);
+            }
+            return Ok(context);
+        }
+
+        let mut error = None;
+        for propagator in &self.propagators {
+            match track!(propagator.extract(carrier)) {
+                Ok(None) => {}
+                Ok(Some(context)) => return Ok(Some(context)),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        error.map_or(Ok(None), Err)
+    }
+
+    fn extract_baggage(&self, carrier: &dyn Extractor) -> Result<Vec<BaggageItem>> {
+        let mut items: Vec<BaggageItem> = Vec::new();
+        for propagator in &self.propagators {
+            for item in track!(propagator.extract_baggage(carrier))? {
+                if items.iter().all(|x| x.name() != item.name()) {
+                    items.push(item);
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    fn extract_with_baggage(&self, carrier: &dyn Extractor) -> ExtractResult {
+        let (context, items, baggage_error) = self.extract_all(carrier);
+        (context, baggage_error.map_or(Ok(items), Err))
+    }
+}
+impl fmt::Debug for CompositePropagator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CompositePropagator")
+            .field("propagators", &self.propagators.len())
+            .field("merge_baggage", &self.merge_baggage)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::propagation::{
+        B3Propagator, BaggagePropagator, JaegerPropagator, TraceContextPropagator,
+    };
+    use crate::state::TraceId;
+    use crate::ErrorKind;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn propagator() -> CompositePropagator {
+        CompositePropagator::new()
+            .propagator(TraceContextPropagator)
+            .propagator(B3Propagator::new())
+            .propagator(JaegerPropagator)
+            .propagator(BaggagePropagator)
+    }
+
+    #[test]
+    fn inject_writes_all_formats() {
+        let state = SpanContextState::new(TraceId { high: 0, low: 1 }, 2).with_flags(1);
+        let context = SpanContext::new(state, vec![BaggageItem::new("k", "v")]);
+
+        let mut carrier = HashMap::new();
+        propagator()
+            .inject_to_http_header(&context, &mut carrier)
+            .unwrap();
+        for key in [
+            "traceparent",
+            "x-b3-traceid",
+            "uber-trace-id",
+            "uberctx-k",
+            "baggage",
+        ] {
+            assert!(carrier.contains_key(key), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn extract_returns_first_valid_context() {
+        let mut carrier = HashMap::new();
+        carrier.insert("traceparent".to_owned(), "malformed".to_owned());
+        carrier.insert("x-b3-traceid".to_owned(), "0000000000000001".to_owned());
+        carrier.insert("x-b3-spanid".to_owned(), "0000000000000002".to_owned());
+        carrier.insert("uber-trace-id".to_owned(), "1:3:0:1".to_owned());
+        carrier.insert("uberctx-a".to_owned(), "jaeger".to_owned());
+        carrier.insert("baggage".to_owned(), "a=w3c,b=w3c".to_owned());
+
+        let context = propagator()
+            .extract_from_http_header(&carrier)
+            .unwrap()
+            .unwrap();
+        assert_eq!(context.state().span_id(), 2);
+        assert!(context.baggage_items().is_empty());
+
+        let context = propagator()
+            .merge_baggage(true)
+            .extract_from_http_header(&carrier)
+            .unwrap()
+            .unwrap();
+        let items = context
+            .baggage_items()
+            .iter()
+            .map(|x| (x.name(), x.value()))
+            .collect::<Vec<_>>();
+        assert_eq!(items, [("a", "jaeger"), ("b", "w3c")]);
+
+        carrier.retain(|k, _| k == "traceparent");
+        let e = propagator()
+            .extract_from_http_header(&carrier)
+            .err()
+            .unwrap();
+        assert_eq!(*e.kind(), ErrorKind::MalformedHeader);
+
+        carrier.clear();
+        assert!(propagator()
+            .extract_from_http_header(&carrier)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn extract_parses_carrier_once() {
+        struct Counting(Arc<AtomicUsize>);
+        impl Propagator for Counting {
+            fn inject(
+                &self,
+                context: &SpanContext<SpanContextState>,
+                carrier: &mut dyn Injector,
+            ) -> Result<()> {
+                JaegerPropagator.inject(context, carrier)
+            }
+            fn extract(
+                &self,
+                carrier: &dyn Extractor,
+            ) -> Result<Option<SpanContext<SpanContextState>>> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                JaegerPropagator.extract(carrier)
+            }
+        }
+
+        let mut carrier = HashMap::new();
+        carrier.insert("uber-trace-id".to_owned(), "1:3:0:1".to_owned());
+        carrier.insert("uberctx-a".to_owned(), "jaeger".to_owned());
+        carrier.insert("baggage".to_owned(), "a=w3c,b=w3c".to_owned());
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let propagator = CompositePropagator::new()
+            .propagator(Counting(Arc::clone(&count)))
+            .propagator(BaggagePropagator)
+            .merge_baggage(true);
+        let context = propagator
+            .extract_from_http_header(&carrier)
+            .unwrap()
+            .unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        let items = context
+            .baggage_items()
+            .iter()
+            .map(|x| (x.name(), x.value()))
+            .collect::<Vec<_>>();
+        assert_eq!(items, [("a", "jaeger"), ("b", "w3c")]);
+    }
+}
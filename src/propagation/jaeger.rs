@@ -0,0 +1,204 @@
+use super::{percent_decode, percent_encode, Extractor, Injector, Propagator};
+use crate::span::{BaggageItem, SpanContext};
+use crate::state::{SpanContextState, TraceId};
+use crate::{Error, ErrorKind, Result};
+use trackable::error::ErrorKindExt;
+
+const UBER_TRACE_ID: &str = "uber-trace-id";
+const UBERCTX_PREFIX: &str = "uberctx-";
+
+/// [Jaeger][jaeger] propagator.
+///
+/// This injects and extracts the `uber-trace-id` field
+/// (`{trace-id}:{span-id}:{parent-span-id}:{flags}`)
+/// and the baggage items as the `uberctx-{name}` fields whose values are percent-encoded.
+///
+/// The debug flag in the `uber-trace-id` field is mapped onto `SpanContext::sampling_priority()`,
+/// so the spans which refer to the extracted context are always sampled.
+///
+/// Note that the baggage items can be extracted from a **Text Map** carrier
+/// only if it implements `TextMap::keys()`.
+///
+/// [jaeger]: https://www.jaegertracing.io/docs/latest/client-libraries/#propagation-format
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::propagation::{JaegerPropagator, Propagator};
+/// use rustracing::state::SpanContextState;
+/// use std::collections::HashMap;
+///
+/// let mut headers = HashMap::new();
+/// headers.insert("uber-trace-id".to_owned(), "6309ab92c95468c:6309ab92c95468c:0:1".to_owned());
+/// headers.insert("uberctx-user".to_owned(), "foo%20bar".to_owned());
+///
+/// let context = JaegerPropagator.extract_from_http_header(&headers).unwrap().unwrap();
+/// assert_eq!(context.state().span_id(), 0x6309ab92c95468c);
+/// assert!(context.state().is_sampled());
+/// assert_eq!(context.baggage_items()[0].value(), "foo bar");
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct JaegerPropagator;
+impl Propagator for JaegerPropagator {
+    fn inject(
+        &self,
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut dyn Injector,
+    ) -> Result<()> {
+        let state = context.state();
+        track_assert!(state.is_valid(), ErrorKind::InvalidInput; state);
+        let trace_id = state.trace_id();
+        let trace_id = if trace_id.high == 0 {
+            format!("{:x}", trace_id.low)
+        } else {
+            format!("{:x}{:016x}", trace_id.high, trace_id.low)
+        };
+        let value = format!(
+            "{}:{:x}:{:x}:{:x}",
+            trace_id,
+            state.span_id(),
+            state.parent_span_id().unwrap_or(0),
+            state.flags()
+        );
+        track!(carrier.set(UBER_TRACE_ID, &value))?;
+        for item in context.baggage_items() {
+            let name = format!("{}{}", UBERCTX_PREFIX, item.name());
+            track!(carrier.set(&name, &percent_encode(item.value())))?;
+        }
+        Ok(())
+    }
+
+    fn extract(&self, carrier: &dyn Extractor) -> Result<Option<SpanContext<SpanContextState>>> {
+        let state = if let Some(value) = carrier.get(UBER_TRACE_ID) {
+            track!(parse_uber_trace_id(value))?
+        } else {
+            return Ok(None);
+        };
+
+        let mut baggage_items = Vec::new();
+        for key in carrier.keys() {
+            let is_baggage = key.len() > UBERCTX_PREFIX.len()
+                && key.is_char_boundary(UBERCTX_PREFIX.len())
+                && key[..UBERCTX_PREFIX.len()].eq_ignore_ascii_case(UBERCTX_PREFIX);
+            if !is_baggage {
+                continue;
+            }
+            if let Some(value) = carrier.get(key) {
+                let value = track!(percent_decode(value, true))?;
+                baggage_items.push(BaggageItem::new(&key[UBERCTX_PREFIX.len()..], &value));
+            }
+        }
+
+        let is_debug = state.is_debug();
+        let mut context = SpanContext::new(state, baggage_items);
+        if is_debug {
+            context.set_sampling_priority(1);
+        }
+        Ok(Some(context))
+    }
+}
+
+fn parse_uber_trace_id(value: &str) -> Result<SpanContextState> {
+    let decoded = track!(percent_decode(value.trim(), true))?;
+    let parts = decoded.split(':').collect::<Vec<_>>();
+    track_assert_eq!(parts.len(), 4, ErrorKind::MalformedHeader; value);
+
+    track_assert!(parts[0].len() <= 32, ErrorKind::MalformedHeader; value);
+    let trace_id = parts[0]
+        .parse::<TraceId>()
+        .map_err(|e| track!(Error::from(ErrorKind::MalformedHeader.takes_over(e))))?;
+    let span_id = track!(parse_hex_u64(parts[1]))?;
+    let parent_span_id = track!(parse_hex_u64(parts[2]))?;
+    track_assert!(parts[3].len() <= 2, ErrorKind::MalformedHeader; value);
+    let flags = track!(parse_hex_u64(parts[3]))? as u8;
+    track_assert!(trace_id.is_valid(), ErrorKind::MalformedHeader; value);
+    track_assert_ne!(span_id, 0, ErrorKind::MalformedHeader; value);
+    Ok(SpanContextState::new(trace_id, span_id)
+        .with_parent_span_id(parent_span_id)
+        .with_flags(flags)
+        .with_remote(true))
+}
+
+fn parse_hex_u64(s: &str) -> Result<u64> {
+    track_assert!(!s.is_empty() && s.len() <= 16, ErrorKind::MalformedHeader; s);
+    track_assert!(s.bytes().all(|b| b.is_ascii_hexdigit()), ErrorKind::MalformedHeader; s);
+    u64::from_str_radix(s, 16).map_err(|e| track!(Error::from(ErrorKind::MalformedHeader.cause(e))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{FLAG_DEBUG, FLAG_SAMPLED};
+    use std::collections::{BTreeMap, HashMap};
+
+    #[test]
+    fn extract_works() {
+        let mut carrier = HashMap::new();
+        carrier.insert(
+            "Uber-Trace-Id".to_owned(),
+            "1a2b3c4d5e6f70819293a4b5c6d7e8f9%3A1f%3A2e%3A3".to_owned(),
+        );
+        carrier.insert("UberCtx-Key".to_owned(), "a%2Cb+c".to_owned());
+        let context = JaegerPropagator
+            .extract_from_http_header(&carrier)
+            .unwrap()
+            .unwrap();
+        let state = context.state();
+        assert_eq!(
+            state.trace_id().to_string(),
+            "1a2b3c4d5e6f70819293a4b5c6d7e8f9"
+        );
+        assert_eq!(state.span_id(), 0x1f);
+        assert_eq!(state.parent_span_id(), Some(0x2e));
+        assert_eq!(state.flags(), FLAG_SAMPLED | FLAG_DEBUG);
+        assert_eq!(context.sampling_priority(), Some(1));
+        assert_eq!(context.baggage_items()[0].name(), "Key");
+        assert_eq!(context.baggage_items()[0].value(), "a,b c");
+
+        for value in [
+            "1:2:3",
+            "0:1:0:1",
+            "1:0:0:1",
+            "1:2:3:100",
+            "x:1:0:1",
+            "1:2:3:4:5",
+        ] {
+            let mut carrier = HashMap::new();
+            carrier.insert("uber-trace-id".to_owned(), value.to_owned());
+            let e = JaegerPropagator
+                .extract_from_http_header(&carrier)
+                .err()
+                .unwrap();
+            assert_eq!(*e.kind(), ErrorKind::MalformedHeader, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn inject_works() {
+        let state = SpanContextState::new(TraceId { high: 0, low: 0xab }, 0xcd).with_flags(1);
+        let context = SpanContext::new(state, vec![BaggageItem::new("user", "foo bar/baz")]);
+
+        let mut carrier = BTreeMap::new();
+        JaegerPropagator
+            .inject_to_text_map(&context, &mut carrier)
+            .unwrap();
+        assert_eq!(
+            carrier.get("uber-trace-id").map(|v| v.as_str()),
+            Some("ab:cd:0:1")
+        );
+        assert_eq!(
+            carrier.get("uberctx-user").map(|v| v.as_str()),
+            Some("foo%20bar%2Fbaz")
+        );
+
+        let extracted = JaegerPropagator
+            .extract_from_text_map(&carrier)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            extracted.state(),
+            &context.state().clone().with_remote(true)
+        );
+        assert_eq!(extracted.baggage_items()[0].value(), "foo bar/baz");
+    }
+}
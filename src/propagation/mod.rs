@@ -0,0 +1,403 @@
+//! Built-in propagation formats for `SpanContextState`.
+//!
+//! Each format is implemented as a `Propagator` which injects `SpanContext<SpanContextState>` to
+//! (and extracts it from) the **Text Map** and **HTTP Header** carriers defined in the `carrier` module.
+//!
+//! # Binary format
+//!
+//! `SpanContextState` also implements `InjectToBinary` and `ExtractFromBinary` with the following encoding
+//! (all integers are big-endian):
+//!
+//! ```text
+//! context = version:u8 (=0) body-length:u32 body
+//! body    = *(field-id:u8 payload-length:u16 payload)
+//! ```
+//!
+//! | field-id | payload                                                  |
+//! |----------|----------------------------------------------------------|
+//! | 0        | trace identifier (16 bytes)                              |
+//! | 1        | span identifier (8 bytes)                                |
+//! | 2        | parent span identifier (8 bytes, optional)               |
+//! | 3        | flags (1 byte)                                           |
+//! | 4        | `tracestate` value (UTF-8, optional)                     |
+//! | 5        | baggage item: name-length:u16 name value (UTF-8, repeated) |
+//! | 6        | property of the preceding baggage item: name-length:u16 name has-value:u8 [value] (repeated) |
+//!
+//! Fields which have unknown identifiers are skipped for forward compatibility.
+//! Since the context is length-prefixed, it can be embedded in a message frame
+//! followed by arbitrary data.
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::propagation::{Propagator, TraceContextPropagator};
+//! use rustracing::sampler::AllSampler;
+//! use rustracing::span::SpanContext;
+//! use rustracing::state::SpanContextState;
+//! use rustracing::Tracer;
+//! use std::collections::HashMap;
+//!
+//! let (tracer, _span_rx) = Tracer::<_, SpanContextState>::new(AllSampler);
+//! let span = tracer.span("foo").start();
+//!
+//! let mut headers = HashMap::new();
+//! let propagator = TraceContextPropagator;
+//! propagator
+//!     .inject_to_http_header(span.context().unwrap(), &mut headers)
+//!     .unwrap();
+//! assert!(headers.contains_key("traceparent"));
+//!
+//! let context: SpanContext<SpanContextState> =
+//!     propagator.extract_from_http_header(&headers).unwrap().unwrap();
+//! assert_eq!(context.state().trace_id(), span.context().unwrap().state().trace_id());
+//! ```
+use crate::carrier::{
+    ExtractFromHttpHeader, ExtractFromTextMap, InjectToHttpHeader, InjectToTextMap,
+    IterHttpHeaderFields, SetHttpHeaderField, TextMap,
+};
+use crate::span::{BaggageItem, SpanContext};
+use crate::state::SpanContextState;
+use crate::{Error, ErrorKind, Result};
+use std::borrow::Cow;
+use std::sync::OnceLock;
+use trackable::error::ErrorKindExt;
+
+pub use self::b3::B3Propagator;
+pub use self::baggage::{
+    BaggagePropagator, MAX_BAGGAGE_BYTES, MAX_BAGGAGE_MEMBERS, MAX_BAGGAGE_MEMBER_BYTES,
+};
+pub use self::composite::CompositePropagator;
+pub use self::jaeger::JaegerPropagator;
+pub use self::trace_context::TraceContextPropagator;
+
+mod b3;
+mod baggage;
+mod binary;
+mod composite;
+mod jaeger;
+mod trace_context;
+
+/// `Propagator` injects span contexts to carriers and extracts them from carriers in a specific format.
+pub trait Propagator {
+    /// Injects `context` to `carrier`.
+    fn inject(
+        &self,
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut dyn Injector,
+    ) -> Result<()>;
+
+    /// Extracts a span context from `carrier`.
+    ///
+    /// If `carrier` contains no span context of the format, it will return `Ok(None)`.
+    fn extract(&self, carrier: &dyn Extractor) -> Result<Option<SpanContext<SpanContextState>>>;
+
+    /// Extracts baggage items from `carrier`.
+    ///
+    /// The default implementation returns the baggage items of the span context extracted by `extract`.
+    /// Propagators of formats which carry only baggage items override this method.
+    fn extract_baggage(&self, carrier: &dyn Extractor) -> Result<Vec<BaggageItem>> {
+        let context = track!(self.extract(carrier))?;
+        Ok(context.map_or_else(Vec::new, |c| c.baggage_items().to_vec()))
+    }
+
+    /// Extracts a span context and baggage items from `carrier` at once.
+    ///
+    /// The results are the same as the ones of `extract` and `extract_baggage`,
+    /// but `carrier` is parsed only once.
+    /// The default implementation returns the baggage items of the span context extracted by `extract`,
+    /// so propagators which override `extract_baggage` should override this method too.
+    fn extract_with_baggage(&self, carrier: &dyn Extractor) -> ExtractResult {
+        match track!(self.extract(carrier)) {
+            Ok(context) => {
+                let items = context
+                    .as_ref()
+                    .map_or_else(Vec::new, |c| c.baggage_items().to_vec());
+                (Ok(context), Ok(items))
+            }
+            Err(e) => (Err(e.clone()), Err(e)),
+        }
+    }
+
+    /// Injects `context` to the **Text Map** `carrier`.
+    fn inject_to_text_map<C>(
+        &self,
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()>
+    where
+        Self: Sized,
+        C: TextMap,
+    {
+        track!(self.inject(context, &mut TextMapInjector(carrier)))
+    }
+
+    /// Extracts a span context from the **Text Map** `carrier`.
+    fn extract_from_text_map<C>(&self, carrier: &C) -> Result<Option<SpanContext<SpanContextState>>>
+    where
+        Self: Sized,
+        C: TextMap,
+    {
+        track!(self.extract(&TextMapExtractor(carrier)))
+    }
+
+    /// Injects `context` to the **HTTP Header** `carrier`.
+    fn inject_to_http_header<C>(
+        &self,
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()>
+    where
+        Self: Sized,
+        C: SetHttpHeaderField,
+    {
+        track!(self.inject(context, &mut HttpHeaderInjector(carrier)))
+    }
+
+    /// Extracts a span context from the **HTTP Header** `carrier`.
+    fn extract_from_http_header<'a, C>(
+        &self,
+        carrier: &'a C,
+    ) -> Result<Option<SpanContext<SpanContextState>>>
+    where
+        Self: Sized,
+        C: IterHttpHeaderFields<'a>,
+    {
+        track!(self.extract(&HttpHeaderExtractor::new(carrier)))
+    }
+
+    /// Converts into `BoxPropagator`.
+    fn boxed(self) -> BoxPropagator
+    where
+        Self: Sized + Send + Sync + 'static,
+    {
+        Box::new(self)
+    }
+}
+impl Propagator for BoxPropagator {
+    fn inject(
+        &self,
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut dyn Injector,
+    ) -> Result<()> {
+        (**self).inject(context, carrier)
+    }
+    fn extract(&self, carrier: &dyn Extractor) -> Result<Option<SpanContext<SpanContextState>>> {
+        (**self).extract(carrier)
+    }
+    fn extract_baggage(&self, carrier: &dyn Extractor) -> Result<Vec<BaggageItem>> {
+        (**self).extract_baggage(carrier)
+    }
+    fn extract_with_baggage(&self, carrier: &dyn Extractor) -> ExtractResult {
+        (**self).extract_with_baggage(carrier)
+    }
+    fn boxed(self) -> BoxPropagator
+    where
+        Self: Sized + Send + Sync + 'static,
+    {
+        self
+    }
+}
+
+/// Boxed version of `Propagator`.
+pub type BoxPropagator = Box<dyn Propagator + Send + Sync + 'static>;
+
+/// The results of extracting a span context and baggage items (see `Propagator::extract_with_baggage()`).
+pub type ExtractResult = (
+    Result<Option<SpanContext<SpanContextState>>>,
+    Result<Vec<BaggageItem>>,
+);
+
+/// `SpanContextState` is injected in the W3C Trace Context and W3C Baggage formats by default.
+///
+/// On extraction, the W3C Trace Context, B3 and Jaeger formats are tried in order,
+/// and the baggage items of all the formats are merged into the extracted context.
+/// Malformed baggage fields (e.g., a `baggage` field exceeding the limits) are ignored
+/// and do not prevent the span context from being extracted.
+impl<C: TextMap> InjectToTextMap<C> for SpanContextState {
+    fn inject_to_text_map(context: &SpanContext<Self>, carrier: &mut C) -> Result<()> {
+        track!(DefaultPropagator::get().inject_to_text_map(context, carrier))
+    }
+}
+impl<C: TextMap> ExtractFromTextMap<C> for SpanContextState {
+    fn extract_from_text_map(carrier: &C) -> Result<Option<SpanContext<Self>>> {
+        track!(DefaultPropagator::get().extract_from_text_map(carrier))
+    }
+}
+impl<C: SetHttpHeaderField> InjectToHttpHeader<C> for SpanContextState {
+    fn inject_to_http_header(context: &SpanContext<Self>, carrier: &mut C) -> Result<()> {
+        track!(DefaultPropagator::get().inject_to_http_header(context, carrier))
+    }
+}
+impl<'a, C: IterHttpHeaderFields<'a>> ExtractFromHttpHeader<'a, C> for SpanContextState {
+    fn extract_from_http_header(carrier: &'a C) -> Result<Option<SpanContext<Self>>> {
+        track!(DefaultPropagator::get().extract_from_http_header(carrier))
+    }
+}
+
+#[derive(Debug)]
+struct DefaultPropagator {
+    extractor: CompositePropagator,
+}
+impl DefaultPropagator {
+    fn get() -> &'static Self {
+        static INSTANCE: OnceLock<DefaultPropagator> = OnceLock::new();
+        INSTANCE.get_or_init(|| DefaultPropagator {
+            extractor: CompositePropagator::new()
+                .propagator(TraceContextPropagator)
+                .propagator(BaggagePropagator)
+                .propagator(B3Propagator::new())
+                .propagator(JaegerPropagator)
+                .merge_baggage(true),
+        })
+    }
+}
+impl Propagator for DefaultPropagator {
+    fn inject(
+        &self,
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut dyn Injector,
+    ) -> Result<()> {
+        track!(TraceContextPropagator.inject(context, carrier))?;
+        track!(BaggagePropagator.inject(context, carrier))
+    }
+
+    fn extract(&self, carrier: &dyn Extractor) -> Result<Option<SpanContext<SpanContextState>>> {
+        track!(self.extractor.extract(carrier))
+    }
+
+    fn extract_with_baggage(&self, carrier: &dyn Extractor) -> ExtractResult {
+        self.extractor.extract_with_baggage(carrier)
+    }
+}
+
+/// This trait allows propagators to write fields to a carrier.
+pub trait Injector {
+    /// Sets the value of the field named `key` to `value`.
+    fn set(&mut self, key: &str, value: &str) -> Result<()>;
+}
+
+/// This trait allows propagators to read fields from a carrier.
+pub trait Extractor {
+    /// Gets the value of the field named `key`.
+    ///
+    /// The name comparison is case-insensitive if the carrier is a HTTP header.
+    fn get(&self, key: &str) -> Option<&str>;
+
+    /// Returns the names of the fields in the carrier.
+    fn keys(&self) -> Vec<&str>;
+}
+
+#[derive(Debug)]
+struct TextMapInjector<'a, C: 'a>(&'a mut C);
+impl<'a, C: TextMap> Injector for TextMapInjector<'a, C> {
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        self.0.set(key, value);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct TextMapExtractor<'a, C: 'a>(&'a C);
+impl<'a, C: TextMap> Extractor for TextMapExtractor<'a, C> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key)
+    }
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys()
+    }
+}
+
+#[derive(Debug)]
+struct HttpHeaderInjector<'a, C: 'a>(&'a mut C);
+impl<'a, C: SetHttpHeaderField> Injector for HttpHeaderInjector<'a, C> {
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        track!(self.0.set_http_header_field(key, value))
+    }
+}
+
+/// Fields which have the same name are combined into a comma-separated value (cf. RFC 7230).
+/// Fields whose values are not valid UTF-8 strings are ignored.
+#[derive(Debug)]
+struct HttpHeaderExtractor<'a> {
+    fields: Vec<(&'a str, Cow<'a, str>)>,
+}
+impl<'a> HttpHeaderExtractor<'a> {
+    fn new<C>(carrier: &'a C) -> Self
+    where
+        C: IterHttpHeaderFields<'a>,
+    {
+        let mut fields: Vec<(&'a str, Cow<'a, str>)> = Vec::new();
+        for (name, value) in carrier.fields() {
+            let value = if let Ok(value) = std::str::from_utf8(value) {
+                value
+            } else {
+                continue;
+            };
+            if let Some(field) = fields.iter_mut().find(|f| f.0.eq_ignore_ascii_case(name)) {
+                let combined = field.1.to_mut();
+                combined.push(',');
+                combined.push_str(value);
+            } else {
+                fields.push((name, Cow::Borrowed(value)));
+            }
+        }
+        HttpHeaderExtractor { fields }
+    }
+}
+impl<'a> Extractor for HttpHeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|f| f.0.eq_ignore_ascii_case(key))
+            .map(|f| f.1.as_ref())
+    }
+    fn keys(&self) -> Vec<&str> {
+        self.fields.iter().map(|f| f.0).collect()
+    }
+}
+
+/// Encodes `s` by replacing the characters other than the unreserved ones (cf. RFC 3986)
+/// with percent-encoded octets.
+fn percent_encode(s: &str) -> String {
+    percent_encode_if(s, |b| {
+        !(b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~'))
+    })
+}
+
+/// Encodes the bytes in `s` which satisfy `f` as percent-encoded octets.
+fn percent_encode_if<F>(s: &str, f: F) -> String
+where
+    F: Fn(u8) -> bool,
+{
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if f(b) {
+            encoded.push_str(&format!("%{:02X}", b));
+        } else {
+            encoded.push(char::from(b));
+        }
+    }
+    encoded
+}
+
+/// Decodes percent-encoded octets in `s`.
+///
+/// If `plus_as_space` is `true`, `'+'` is decoded as a space
+/// for compatibility with `application/x-www-form-urlencoded` encoders.
+fn percent_decode(s: &str, plus_as_space: bool) -> Result<String> {
+    let mut decoded = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                let hi = bytes.next().and_then(|b| char::from(b).to_digit(16));
+                let lo = bytes.next().and_then(|b| char::from(b).to_digit(16));
+                let (hi, lo) = track_assert_some!(hi.zip(lo), ErrorKind::MalformedHeader; s);
+                decoded.push((hi * 16 + lo) as u8);
+            }
+            b'+' if plus_as_space => decoded.push(b' '),
+            _ => decoded.push(b),
+        }
+    }
+    String::from_utf8(decoded).map_err(|e| track!(Error::from(ErrorKind::MalformedHeader.cause(e))))
+}
@@ -0,0 +1,221 @@
+use super::{Extractor, Injector, Propagator};
+use crate::span::SpanContext;
+use crate::state::{SpanContextState, TraceId, TraceState, FLAG_SAMPLED};
+use crate::{Error, ErrorKind, Result};
+use trackable::error::ErrorKindExt;
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+const TRACEPARENT_LEN: usize = 55;
+
+/// [W3C Trace Context][trace-context] propagator.
+///
+/// This injects and extracts the `traceparent` and `tracestate` fields.
+///
+/// The `traceparent` field is parsed strictly:
+/// if it is malformed, `extract` will return an error with the kind `ErrorKind::MalformedHeader`.
+/// Fields of future versions are accepted as long as their prefix is compatible with the version `00`.
+///
+/// The entries of the `tracestate` field (including ones of unknown vendors)
+/// are kept in `SpanContextState::trace_state()` and inherited by the descendant spans,
+/// so they are propagated to the next hop as is.
+/// If the `tracestate` field is malformed, it is discarded as the specification requires.
+///
+/// [trace-context]: https://www.w3.org/TR/trace-context/
+#[derive(Debug, Default, Clone)]
+pub struct TraceContextPropagator;
+impl Propagator for TraceContextPropagator {
+    fn inject(
+        &self,
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut dyn Injector,
+    ) -> Result<()> {
+        let state = context.state();
+        track_assert!(state.is_valid(), ErrorKind::InvalidInput; state);
+        let traceparent = format!(
+            "00-{}-{:016x}-{:02x}",
+            state.trace_id(),
+            state.span_id(),
+            state.flags() & FLAG_SAMPLED
+        );
+        track!(carrier.set(TRACEPARENT, &traceparent))?;
+        if !state.trace_state().is_empty() {
+            track!(carrier.set(TRACESTATE, &state.trace_state().to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn extract(&self, carrier: &dyn Extractor) -> Result<Option<SpanContext<SpanContextState>>> {
+        let traceparent = if let Some(value) = carrier.get(TRACEPARENT) {
+            value
+        } else {
+            return Ok(None);
+        };
+        let trace_state = carrier
+            .get(TRACESTATE)
+            .and_then(|value| value.parse::<TraceState>().ok())
+            .unwrap_or_default();
+        let state = track!(parse_traceparent(traceparent))?.with_trace_state(trace_state);
+        Ok(Some(SpanContext::new(state, Vec::new())))
+    }
+}
+
+fn parse_traceparent(value: &str) -> Result<SpanContextState> {
+    let value = value.trim_matches(|c| c == ' ' || c == '\t');
+    track_assert!(value.is_ascii(), ErrorKind::MalformedHeader; value);
+    track_assert!(value.len() >= TRACEPARENT_LEN, ErrorKind::MalformedHeader; value);
+
+    let version = &value[0..2];
+    track_assert!(is_lower_hex(version), ErrorKind::MalformedHeader; value);
+    track_assert_ne!(version, "ff", ErrorKind::MalformedHeader; value);
+    if version == "00" {
+        track_assert_eq!(value.len(), TRACEPARENT_LEN, ErrorKind::MalformedHeader; value);
+    } else if value.len() > TRACEPARENT_LEN {
+        track_assert_eq!(
+            value.as_bytes()[TRACEPARENT_LEN],
+            b'-',
+            ErrorKind::MalformedHeader;
+            value
+        );
+    }
+
+    let bytes = value.as_bytes();
+    track_assert!(
+        bytes[2] == b'-' && bytes[35] == b'-' && bytes[52] == b'-',
+        ErrorKind::MalformedHeader;
+        value
+    );
+    let trace_id = &value[3..35];
+    let span_id = &value[36..52];
+    let flags = &value[53..55];
+    track_assert!(
+        is_lower_hex(trace_id) && is_lower_hex(span_id) && is_lower_hex(flags),
+        ErrorKind::MalformedHeader;
+        value
+    );
+
+    let trace_id = track!(trace_id.parse::<TraceId>())?;
+    let span_id = u64::from_str_radix(span_id, 16)
+        .map_err(|e| track!(Error::from(ErrorKind::MalformedHeader.cause(e))))?;
+    let flags = u8::from_str_radix(flags, 16)
+        .map_err(|e| track!(Error::from(ErrorKind::MalformedHeader.cause(e))))?;
+    track_assert!(trace_id.is_valid(), ErrorKind::MalformedHeader; value);
+    track_assert_ne!(span_id, 0, ErrorKind::MalformedHeader; value);
+    Ok(SpanContextState::new(trace_id, span_id)
+        .with_flags(flags & FLAG_SAMPLED)
+        .with_remote(true))
+}
+
+fn is_lower_hex(s: &str) -> bool {
+    s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn extract(fields: &[(&str, &str)]) -> Result<Option<SpanContext<SpanContextState>>> {
+        let carrier = fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        TraceContextPropagator.extract_from_http_header(&carrier)
+    }
+
+    #[test]
+    fn extract_works() {
+        let context = extract(&[
+            (
+                "Traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            ),
+            ("tracestate", "congo=t61rcWkgMzE,unknown=foo"),
+        ])
+        .unwrap()
+        .unwrap();
+        let state = context.state();
+        assert_eq!(
+            state.trace_id().to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(state.span_id(), 0x00f0_67aa_0ba9_02b7);
+        assert!(state.is_sampled());
+        assert_eq!(state.trace_state().get("unknown"), Some("foo"));
+
+        assert!(extract(&[]).unwrap().is_none());
+
+        // Future versions
+        let context = extract(&[(
+            "traceparent",
+            "cc-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-03-what-the-future-will-be",
+        )])
+        .unwrap()
+        .unwrap();
+        assert_eq!(context.state().flags(), FLAG_SAMPLED);
+
+        // Malformed `tracestate` is ignored
+        let context = extract(&[
+            (
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+            ),
+            ("tracestate", "INVALID"),
+        ])
+        .unwrap()
+        .unwrap();
+        assert!(!context.state().is_sampled());
+        assert!(context.state().trace_state().is_empty());
+    }
+
+    #[test]
+    fn extract_malformed_traceparent_fails() {
+        for value in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-0",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "cc-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01.",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00_4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-0g",
+        ] {
+            let e = extract(&[("traceparent", value)]).err().unwrap();
+            assert_eq!(*e.kind(), ErrorKind::MalformedHeader, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn inject_works() {
+        let mut trace_state = TraceState::new();
+        trace_state.insert("rojo", "00f067aa0ba902b7").unwrap();
+        let state = SpanContextState::new(TraceId { high: 1, low: 2 }, 3)
+            .with_flags(FLAG_SAMPLED)
+            .with_trace_state(trace_state);
+        let context = SpanContext::new(state, Vec::new());
+
+        let mut carrier = HashMap::new();
+        TraceContextPropagator
+            .inject_to_text_map(&context, &mut carrier)
+            .unwrap();
+        assert_eq!(
+            carrier.get("traceparent").map(|v| v.as_str()),
+            Some("00-00000000000000010000000000000002-0000000000000003-01")
+        );
+        assert_eq!(
+            carrier.get("tracestate").map(|v| v.as_str()),
+            Some("rojo=00f067aa0ba902b7")
+        );
+
+        let extracted = TraceContextPropagator
+            .extract_from_text_map(&carrier)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            extracted.state(),
+            &context.state().clone().with_remote(true)
+        );
+    }
+}
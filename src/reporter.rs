@@ -0,0 +1,420 @@
+//! Span reporters.
+//!
+//! A reporter drains the finished spans sent by a `Tracer` and passes them to `SpanExporter`s.
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::reporter::BatchReporter;
+//! use rustracing::sampler::AllSampler;
+//! use rustracing::span::FinishedSpan;
+//! use rustracing::Tracer;
+//! use std::sync::{Arc, Mutex};
+//!
+//! let exported = Arc::new(Mutex::new(Vec::new()));
+//! let exported_for_exporter = Arc::clone(&exported);
+//! let reporter = BatchReporter::builder()
+//!     .exporter(move |spans: &[FinishedSpan<()>]| {
+//!         let mut exported = exported_for_exporter.lock().unwrap();
+//!         exported.extend(spans.iter().map(|s| s.operation_name().to_owned()));
+//!         Ok(())
+//!     })
+//!     .build()
+//!     .unwrap();
+//!
+//! let tracer = Tracer::with_sender(AllSampler, reporter.span_sender());
+//! {
+//!     let _span = tracer.span("foo").start_with_state(());
+//! }
+//! reporter.shutdown().unwrap();
+//! assert_eq!(*exported.lock().unwrap(), ["foo"]);
+//! ```
+use crate::span::{FinishedSpan, SpanReceiver, SpanSender};
+use crate::{Error, ErrorKind, Result};
+use crossbeam_channel::{self, Receiver, Sender};
+use std::fmt;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use trackable::error::ErrorKindExt;
+
+/// `SpanExporter` exports finished spans to a destination (e.g., a tracing backend).
+pub trait SpanExporter<T>: Send {
+    /// Exports the batch of `spans`.
+    fn export(&mut self, spans: &[FinishedSpan<T>]) -> Result<()>;
+
+    /// Flushes the spans buffered in this exporter (if any).
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Shuts down this exporter.
+    ///
+    /// This is called once when the associated reporter is shut down.
+    /// The default implementation calls `flush()`.
+    fn shutdown(&mut self) -> Result<()> {
+        track!(self.flush())
+    }
+}
+impl<T, F> SpanExporter<T> for F
+where
+    F: FnMut(&[FinishedSpan<T>]) -> Result<()> + Send,
+{
+    fn export(&mut self, spans: &[FinishedSpan<T>]) -> Result<()> {
+        track!(self(spans))
+    }
+}
+
+/// Boxed version of `SpanExporter`.
+pub type BoxSpanExporter<T> = Box<dyn SpanExporter<T> + 'static>;
+
+/// Builder of `BatchReporter`.
+pub struct BatchReporterBuilder<T> {
+    exporters: Vec<BoxSpanExporter<T>>,
+    batch_size: usize,
+    flush_interval: Duration,
+    max_queue_size: usize,
+}
+impl<T: Send + 'static> BatchReporterBuilder<T> {
+    /// The default value of `batch_size`.
+    pub const DEFAULT_BATCH_SIZE: usize = 512;
+
+    /// The default value of `flush_interval`.
+    pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// The default value of `max_queue_size`.
+    pub const DEFAULT_MAX_QUEUE_SIZE: usize = 2048;
+
+    /// Makes a new `BatchReporterBuilder` instance which has no exporters.
+    pub fn new() -> Self {
+        BatchReporterBuilder {
+            exporters: Vec::new(),
+            batch_size: Self::DEFAULT_BATCH_SIZE,
+            flush_interval: Self::DEFAULT_FLUSH_INTERVAL,
+            max_queue_size: Self::DEFAULT_MAX_QUEUE_SIZE,
+        }
+    }
+
+    /// Adds `exporter` to which batches of finished spans are passed.
+    pub fn exporter<E>(mut self, exporter: E) -> Self
+    where
+        E: SpanExporter<T> + 'static,
+    {
+        self.exporters.push(Box::new(exporter));
+        self
+    }
+
+    /// Sets the maximum number of spans in a batch.
+    ///
+    /// When the number of the buffered spans reaches this value, they are exported immediately.
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// Sets the interval at which the buffered spans are exported even if the batch is not full.
+    ///
+    /// The interval must be positive.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// Sets the capacity of the channel between tracers and the reporter.
+    ///
+    /// Spans finished while the channel is full are dropped.
+    pub fn max_queue_size(mut self, size: usize) -> Self {
+        self.max_queue_size = size;
+        self
+    }
+
+    /// Builds a `BatchReporter` and spawns its background worker thread.
+    ///
+    /// # Errors
+    ///
+    /// If the flush interval is zero, it will return an error with the kind `ErrorKind::InvalidInput`.
+    /// If the worker thread cannot be spawned, it will return an error with the kind `ErrorKind::Other`.
+    pub fn build(self) -> Result<BatchReporter<T>> {
+        track_assert_ne!(self.flush_interval, Duration::ZERO, ErrorKind::InvalidInput);
+        let (span_tx, span_rx) = crossbeam_channel::bounded(self.max_queue_size);
+        let (command_tx, command_rx) = crossbeam_channel::unbounded();
+        let worker = Worker {
+            span_rx,
+            command_rx,
+            exporters: self.exporters,
+            batch: Vec::with_capacity(self.batch_size),
+            batch_size: self.batch_size,
+            flush_interval: self.flush_interval,
+            error: None,
+        };
+        let handle = thread::Builder::new()
+            .name("rustracing-reporter".to_owned())
+            .spawn(move || worker.run())
+            .map_err(|e| track!(Error::from(ErrorKind::Other.cause(e))))?;
+        Ok(BatchReporter {
+            span_tx,
+            command_tx,
+            handle: Some(handle),
+        })
+    }
+}
+impl<T: Send + 'static> Default for BatchReporterBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T> fmt::Debug for BatchReporterBuilder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BatchReporterBuilder")
+            .field("exporters", &self.exporters.len())
+            .field("batch_size", &self.batch_size)
+            .field("flush_interval", &self.flush_interval)
+            .field("max_queue_size", &self.max_queue_size)
+            .finish()
+    }
+}
+
+/// A reporter which exports finished spans in batches on a background thread.
+///
+/// Use `span_sender()` to get the sender to be passed to `Tracer::with_sender()`.
+///
+/// When this reporter is dropped, it is shut down as `shutdown()` but the result is discarded.
+#[derive(Debug)]
+pub struct BatchReporter<T> {
+    span_tx: SpanSender<T>,
+    command_tx: Sender<Command>,
+    handle: Option<JoinHandle<()>>,
+}
+impl<T: Send + 'static> BatchReporter<T> {
+    /// Makes a new `BatchReporter` instance with the default settings.
+    ///
+    /// # Errors
+    ///
+    /// If the worker thread cannot be spawned, it will return an error with the kind `ErrorKind::Other`.
+    pub fn new<E>(exporter: E) -> Result<Self>
+    where
+        E: SpanExporter<T> + 'static,
+    {
+        track!(BatchReporterBuilder::new().exporter(exporter).build())
+    }
+
+    /// Returns a `BatchReporterBuilder` instance.
+    pub fn builder() -> BatchReporterBuilder<T> {
+        BatchReporterBuilder::new()
+    }
+}
+impl<T> BatchReporter<T> {
+    /// Returns the sender of finished spans to this reporter.
+    pub fn span_sender(&self) -> SpanSender<T> {
+        self.span_tx.clone()
+    }
+
+    /// Exports all the spans queued before this call and flushes the exporters.
+    ///
+    /// This blocks until the exporters complete flushing.
+    ///
+    /// # Errors
+    ///
+    /// It returns the first error that the exporters have reported since the last flush (if any).
+    pub fn flush(&self) -> Result<()> {
+        let (reply_tx, reply_rx) = crossbeam_channel::bounded(1);
+        track!(self.request(Command::Flush(reply_tx), &reply_rx))
+    }
+
+    /// Exports all the queued spans, shuts down the exporters and stops the worker thread.
+    ///
+    /// # Errors
+    ///
+    /// It returns the first error that the exporters have reported since the last flush (if any).
+    pub fn shutdown(mut self) -> Result<()> {
+        track!(self.shutdown_worker())
+    }
+
+    fn shutdown_worker(&mut self) -> Result<()> {
+        let handle = if let Some(handle) = self.handle.take() {
+            handle
+        } else {
+            return Ok(());
+        };
+        let (reply_tx, reply_rx) = crossbeam_channel::bounded(1);
+        let result = self.request(Command::Shutdown(reply_tx), &reply_rx);
+        let _ = handle.join();
+        track!(result)
+    }
+
+    fn request(&self, command: Command, reply_rx: &Receiver<Result<()>>) -> Result<()> {
+        track_assert!(
+            self.command_tx.send(command).is_ok(),
+            ErrorKind::Other,
+            "The reporter thread is down"
+        );
+        match reply_rx.recv() {
+            Ok(result) => track!(result),
+            Err(_) => track_panic!(ErrorKind::Other, "The reporter thread is down"),
+        }
+    }
+}
+impl<T> Drop for BatchReporter<T> {
+    fn drop(&mut self) {
+        let _ = self.shutdown_worker();
+    }
+}
+
+#[derive(Debug)]
+enum Command {
+    Flush(Sender<Result<()>>),
+    Shutdown(Sender<Result<()>>),
+}
+
+struct Worker<T> {
+    span_rx: SpanReceiver<T>,
+    command_rx: Receiver<Command>,
+    exporters: Vec<BoxSpanExporter<T>>,
+    batch: Vec<FinishedSpan<T>>,
+    batch_size: usize,
+    flush_interval: Duration,
+    error: Option<Error>,
+}
+impl<T> Worker<T> {
+    fn run(mut self) {
+        let ticker = crossbeam_channel::tick(self.flush_interval);
+        loop {
+            crossbeam_channel::select! {
+                recv(self.span_rx) -> span => {
+                    if let Ok(span) = span {
+                        self.push(span);
+                    }
+                }
+                recv(ticker) -> _ => self.export(),
+                recv(self.command_rx) -> command => match command {
+                    Ok(Command::Flush(reply_tx)) => {
+                        let _ = reply_tx.send(self.flush());
+                    }
+                    Ok(Command::Shutdown(reply_tx)) => {
+                        let mut result = self.flush();
+                        for exporter in &mut self.exporters {
+                            let r = track!(exporter.shutdown());
+                            result = result.and(r);
+                        }
+                        let _ = reply_tx.send(result);
+                        return;
+                    }
+                    Err(_) => return,
+                },
+            }
+        }
+    }
+
+    fn push(&mut self, span: FinishedSpan<T>) {
+        self.batch.push(span);
+        if self.batch.len() >= self.batch_size {
+            self.export();
+        }
+    }
+
+    fn export(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        for exporter in &mut self.exporters {
+            if let Err(e) = track!(exporter.export(&self.batch)) {
+                self.error.get_or_insert(e);
+            }
+        }
+        self.batch.clear();
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        while let Ok(span) = self.span_rx.try_recv() {
+            self.push(span);
+        }
+        self.export();
+        for exporter in &mut self.exporters {
+            if let Err(e) = track!(exporter.flush()) {
+                self.error.get_or_insert(e);
+            }
+        }
+        self.error.take().map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::Tracer;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn batching_works() {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let batches_for_exporter = Arc::clone(&batches);
+        let reporter = BatchReporter::builder()
+            .exporter(move |spans: &[FinishedSpan<()>]| {
+                batches_for_exporter.lock().unwrap().push(spans.len());
+                Ok(())
+            })
+            .batch_size(2)
+            .flush_interval(Duration::from_secs(3600))
+            .build()
+            .unwrap();
+        let tracer = Tracer::with_sender(AllSampler, reporter.span_sender());
+        for _ in 0..5 {
+            let _span = tracer.span("foo").start_with_state(());
+        }
+
+        reporter.flush().unwrap();
+        assert_eq!(*batches.lock().unwrap(), [2, 2, 1]);
+    }
+
+    #[test]
+    fn flush_interval_works() {
+        let (exported_tx, exported_rx) = crossbeam_channel::unbounded();
+        let reporter = BatchReporter::builder()
+            .exporter(move |spans: &[FinishedSpan<()>]| {
+                let _ = exported_tx.send(spans.len());
+                Ok(())
+            })
+            .flush_interval(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let tracer = Tracer::with_sender(AllSampler, reporter.span_sender());
+        {
+            let _span = tracer.span("foo").start_with_state(());
+        }
+        assert_eq!(exported_rx.recv_timeout(Duration::from_secs(10)), Ok(1));
+
+        let e = BatchReporter::builder()
+            .exporter(|_: &[FinishedSpan<()>]| Ok(()))
+            .flush_interval(Duration::ZERO)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn shutdown_reports_errors() {
+        struct Failing(Arc<Mutex<bool>>);
+        impl SpanExporter<()> for Failing {
+            fn export(&mut self, _spans: &[FinishedSpan<()>]) -> Result<()> {
+                track_panic!(ErrorKind::Other)
+            }
+            fn shutdown(&mut self) -> Result<()> {
+                *self.0.lock().unwrap() = true;
+                Ok(())
+            }
+        }
+
+        let is_shutdown = Arc::new(Mutex::new(false));
+        let reporter = BatchReporter::new(Failing(Arc::clone(&is_shutdown))).unwrap();
+        let tracer = Tracer::with_sender(AllSampler, reporter.span_sender());
+        {
+            let _span = tracer.span("foo").start_with_state(());
+        }
+        assert!(reporter.shutdown().is_err());
+        assert!(*is_shutdown.lock().unwrap());
+
+        // The reporter has gone, so new spans are discarded.
+        let _span = tracer.span("bar").start_with_state(());
+    }
+}
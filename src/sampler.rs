@@ -1,7 +1,14 @@
 //! `Sampler` trait and its built-in implementations.
 use crate::span::CandidateSpan;
-use crate::{ErrorKind, Result};
+use crate::state::TraceId;
+use crate::{Error, ErrorKind, Result};
 use rand::{self, Rng};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use trackable::error::ErrorKindExt;
 
 /// `Sampler` decides whether a new trace should be sampled or not.
 pub trait Sampler<T> {
@@ -73,6 +80,770 @@ impl<T> Sampler<T> for ProbabilisticSampler {
     }
 }
 
+/// Span context state which has a trace identifier.
+pub trait TraceIdState {
+    /// Returns the trace identifier of the span.
+    fn trace_id(&self) -> TraceId;
+}
+
+/// This samples a certain percentage of traces deterministically based on their trace identifiers.
+///
+/// Unlike `ProbabilisticSampler`, every process which uses this sampler with the same rate
+/// makes the same decision for the same trace.
+/// The decision is made from the lower 64 bits of `CandidateSpan::trace_id()`.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::sampler::TraceIdRatioSampler;
+/// use rustracing::span::{Span, SpanContext};
+/// use rustracing::state::{SpanContextState, TraceId};
+/// use rustracing::Tracer;
+///
+/// let sampler = TraceIdRatioSampler::new(0.5).unwrap();
+/// let (tracer, _span_rx) = Tracer::<_, SpanContextState>::new(sampler);
+///
+/// let start_child = |trace_id_low| {
+///     let state = SpanContextState::new(TraceId { high: 0, low: trace_id_low }, 1);
+///     let parent = SpanContext::new(state, Vec::new());
+///     let span: Span<_> = tracer.span("foo").child_of(&parent).start();
+///     span
+/// };
+/// assert!(start_child(1).is_sampled());
+/// assert!(!start_child(u64::MAX).is_sampled());
+/// ```
+#[derive(Debug, Clone)]
+pub struct TraceIdRatioSampler {
+    sampling_rate: f64,
+    upper_bound: u64,
+}
+impl TraceIdRatioSampler {
+    /// Makes a new `TraceIdRatioSampler` instance.
+    ///
+    /// # Errors
+    ///
+    /// If `sampling_rate` is not in the range `0.0...1.0`,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn new(sampling_rate: f64) -> Result<Self> {
+        track_assert!(0.0 <= sampling_rate, ErrorKind::InvalidInput);
+        track_assert!(sampling_rate <= 1.0, ErrorKind::InvalidInput);
+        Ok(TraceIdRatioSampler {
+            sampling_rate,
+            upper_bound: (sampling_rate * (1u64 << 63) as f64) as u64,
+        })
+    }
+
+    /// Returns the sampling rate of this sampler.
+    pub fn sampling_rate(&self) -> f64 {
+        self.sampling_rate
+    }
+
+    fn is_sampled_trace(&self, trace_id: TraceId) -> bool {
+        (trace_id.low >> 1) < self.upper_bound
+    }
+}
+impl<T: TraceIdState> Sampler<T> for TraceIdRatioSampler {
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        self.is_sampled_trace(span.trace_id())
+    }
+}
+
+/// This samples at most a certain number of traces per second.
+///
+/// This is a token bucket which is implemented by using GCRA (generic cell rate algorithm),
+/// so the state of the bucket is a single atomic integer and no lock is needed.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::sampler::{ProbabilisticSampler, RateLimitingSampler, Sampler};
+/// use rustracing::Tracer;
+///
+/// // Samples 10% of traces but at most 100 traces per second (with bursts of up to 10 traces).
+/// let sampler = Sampler::<()>::and(
+///     ProbabilisticSampler::new(0.1).unwrap(),
+///     RateLimitingSampler::new(100.0, 10).unwrap(),
+/// );
+/// let (tracer, _span_rx) = Tracer::new(sampler);
+/// let _span = tracer.span("foo").start_with_state(());
+/// ```
+#[derive(Debug)]
+pub struct RateLimitingSampler {
+    start: Instant,
+    emission_interval: u64,
+    burst_tolerance: u64,
+    theoretical_arrival_time: AtomicU64,
+}
+impl RateLimitingSampler {
+    /// Makes a new `RateLimitingSampler` instance.
+    ///
+    /// It samples `traces_per_second` traces per second on average,
+    /// and allows bursts of up to `burst` traces.
+    ///
+    /// # Errors
+    ///
+    /// If `traces_per_second` is not a positive finite number or `burst` is `0`,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn new(traces_per_second: f64, burst: u32) -> Result<Self> {
+        track_assert!(0.0 < traces_per_second, ErrorKind::InvalidInput);
+        track_assert!(traces_per_second.is_finite(), ErrorKind::InvalidInput);
+        track_assert_ne!(burst, 0, ErrorKind::InvalidInput);
+
+        let emission_interval = ((1_000_000_000.0 / traces_per_second) as u64).max(1);
+        Ok(RateLimitingSampler {
+            start: Instant::now(),
+            emission_interval,
+            burst_tolerance: emission_interval.saturating_mul(u64::from(burst)),
+            theoretical_arrival_time: AtomicU64::new(0),
+        })
+    }
+
+    fn try_acquire(&self) -> bool {
+        let now = self.start.elapsed().as_nanos() as u64;
+        let mut tat = self.theoretical_arrival_time.load(Ordering::Relaxed);
+        loop {
+            let next_tat = tat.max(now).saturating_add(self.emission_interval);
+            if next_tat - now > self.burst_tolerance {
+                return false;
+            }
+            match self.theoretical_arrival_time.compare_exchange_weak(
+                tat,
+                next_tat,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => tat = actual,
+            }
+        }
+    }
+}
+impl<T> Sampler<T> for RateLimitingSampler {
+    fn is_sampled(&self, _span: &CandidateSpan<T>) -> bool {
+        self.try_acquire()
+    }
+}
+
+/// This samples traces by using the samplers selected by the operation names of the spans.
+///
+/// The sampler for an operation is selected as follows:
+/// 1. The sampler registered by `operation()` with the same name.
+/// 2. The sampler registered by the first `pattern()` which matches the name.
+///    In patterns, `*` matches any sequence of characters and `?` matches any single character.
+/// 3. The default sampler.
+///
+/// The results of pattern matching are cached for each operation name.
+/// To bound the memory usage when operation names have a high cardinality,
+/// at most `max_operations` names are cached; the patterns are matched every time for the others.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::sampler::{AllSampler, NullSampler, PerOperationSampler, ProbabilisticSampler};
+/// use rustracing::Tracer;
+///
+/// let sampler = PerOperationSampler::new(ProbabilisticSampler::new(0.1).unwrap())
+///     .operation("/health", NullSampler)
+///     .pattern("/checkout/*", AllSampler);
+/// let (tracer, _span_rx) = Tracer::new(sampler);
+///
+/// assert!(!tracer.span("/health").start_with_state(()).is_sampled());
+/// assert!(tracer.span("/checkout/confirm").start_with_state(()).is_sampled());
+/// ```
+pub struct PerOperationSampler<T> {
+    samplers: Vec<BoxSampler<T>>,
+    operations: HashMap<String, usize>,
+    patterns: Vec<(String, usize)>,
+    default: BoxSampler<T>,
+    max_operations: usize,
+    tracked_operations: RwLock<HashMap<String, Option<usize>>>,
+}
+impl<T> PerOperationSampler<T> {
+    /// The default value of the maximum number of tracked operations.
+    pub const DEFAULT_MAX_OPERATIONS: usize = 2000;
+
+    /// Makes a new `PerOperationSampler` instance which uses `default` for unmatched operations.
+    pub fn new<S>(default: S) -> Self
+    where
+        S: Sampler<T> + Send + Sync + 'static,
+    {
+        PerOperationSampler {
+            samplers: Vec::new(),
+            operations: HashMap::new(),
+            patterns: Vec::new(),
+            default: default.boxed(),
+            max_operations: Self::DEFAULT_MAX_OPERATIONS,
+            tracked_operations: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Uses `sampler` for the operation named `operation_name`.
+    pub fn operation<N, S>(mut self, operation_name: N, sampler: S) -> Self
+    where
+        N: Into<String>,
+        S: Sampler<T> + Send + Sync + 'static,
+    {
+        self.operations
+            .insert(operation_name.into(), self.samplers.len());
+        self.samplers.push(sampler.boxed());
+        self
+    }
+
+    /// Uses `sampler` for the operations whose names match `pattern`.
+    pub fn pattern<P, S>(mut self, pattern: P, sampler: S) -> Self
+    where
+        P: Into<String>,
+        S: Sampler<T> + Send + Sync + 'static,
+    {
+        self.patterns.push((pattern.into(), self.samplers.len()));
+        self.samplers.push(sampler.boxed());
+        self.clear_tracked_operations();
+        self
+    }
+
+    /// Sets the maximum number of operations whose pattern matching results are cached.
+    ///
+    /// The default value is `PerOperationSampler::DEFAULT_MAX_OPERATIONS`.
+    pub fn max_operations(mut self, max: usize) -> Self {
+        self.max_operations = max;
+        self.clear_tracked_operations();
+        self
+    }
+
+    /// Returns the number of the tracked operations.
+    pub fn tracked_operations(&self) -> usize {
+        self.tracked_operations.read().map_or(0, |x| x.len())
+    }
+
+    fn clear_tracked_operations(&mut self) {
+        if let Ok(tracked) = self.tracked_operations.get_mut() {
+            tracked.clear();
+        }
+    }
+
+    fn select(&self, operation_name: &str) -> &BoxSampler<T> {
+        let index = if let Some(&i) = self.operations.get(operation_name) {
+            Some(i)
+        } else {
+            self.match_patterns(operation_name)
+        };
+        index.map_or(&self.default, |i| &self.samplers[i])
+    }
+
+    fn match_patterns(&self, operation_name: &str) -> Option<usize> {
+        if self.patterns.is_empty() {
+            return None;
+        }
+        if let Ok(tracked) = self.tracked_operations.read() {
+            if let Some(&index) = tracked.get(operation_name) {
+                return index;
+            }
+        }
+        let index = self
+            .patterns
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern, operation_name))
+            .map(|&(_, i)| i);
+        if let Ok(mut tracked) = self.tracked_operations.write() {
+            if tracked.len() < self.max_operations {
+                tracked.insert(operation_name.to_owned(), index);
+            }
+        }
+        index
+    }
+}
+impl<T> Sampler<T> for PerOperationSampler<T> {
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        self.select(span.operation_name()).is_sampled(span)
+    }
+}
+impl<T> fmt::Debug for PerOperationSampler<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PerOperationSampler")
+            .field("operations", &self.operations.keys().collect::<Vec<_>>())
+            .field(
+                "patterns",
+                &self.patterns.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+            )
+            .field("max_operations", &self.max_operations)
+            .field("tracked_operations", &self.tracked_operations())
+            .finish()
+    }
+}
+
+/// Returns `true` if `text` matches the glob `pattern` (which supports `*` and `?`).
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    backtrack = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Sampling state of a parent span which `ParentBasedSampler` inspects.
+pub trait ParentState {
+    /// Returns `true` if the parent span is sampled.
+    fn is_sampled(&self) -> bool;
+
+    /// Returns `true` if the parent span has been propagated from a remote process.
+    fn is_remote(&self) -> bool;
+
+    /// Returns `true` if the parent span has deferred the sampling decision to its children.
+    ///
+    /// The default implementation always returns `false`.
+    fn is_deferred(&self) -> bool {
+        false
+    }
+}
+
+/// This samples traces by using the samplers selected by the parents of the spans.
+///
+/// The parent of a span is determined by `CandidateSpan::parent()`.
+/// The sampler for a span is selected as follows:
+///
+/// | parent                 | sampler                       | default       |
+/// |------------------------|-------------------------------|---------------|
+/// | none                   | `root`                        | -             |
+/// | deferred               | `root`                        | -             |
+/// | remote and sampled     | `remote_parent_sampled()`     | `AllSampler`  |
+/// | remote and not sampled | `remote_parent_not_sampled()` | `NullSampler` |
+/// | local and sampled      | `local_parent_sampled()`      | `AllSampler`  |
+/// | local and not sampled  | `local_parent_not_sampled()`  | `NullSampler` |
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::sampler::{ParentBasedSampler, ProbabilisticSampler};
+/// use rustracing::span::SpanContext;
+/// use rustracing::state::{SpanContextState, TraceId};
+/// use rustracing::Tracer;
+///
+/// let sampler = ParentBasedSampler::new(ProbabilisticSampler::new(0.01).unwrap());
+/// let (tracer, _span_rx) = Tracer::<_, SpanContextState>::new(sampler);
+///
+/// // The remote parent is not sampled.
+/// let state = SpanContextState::new(TraceId::new(), 1).with_remote(true);
+/// let parent = SpanContext::new(state, Vec::new());
+/// let span = tracer.span("foo").child_of(&parent).start();
+/// assert!(!span.is_sampled());
+/// ```
+pub struct ParentBasedSampler<T> {
+    root: BoxSampler<T>,
+    remote_parent_sampled: BoxSampler<T>,
+    remote_parent_not_sampled: BoxSampler<T>,
+    local_parent_sampled: BoxSampler<T>,
+    local_parent_not_sampled: BoxSampler<T>,
+}
+impl<T> ParentBasedSampler<T> {
+    /// Makes a new `ParentBasedSampler` instance which uses `root` for the spans
+    /// without parents or with deferred parents.
+    pub fn new<S>(root: S) -> Self
+    where
+        S: Sampler<T> + Send + Sync + 'static,
+    {
+        ParentBasedSampler {
+            root: root.boxed(),
+            remote_parent_sampled: AllSampler.boxed(),
+            remote_parent_not_sampled: NullSampler.boxed(),
+            local_parent_sampled: AllSampler.boxed(),
+            local_parent_not_sampled: NullSampler.boxed(),
+        }
+    }
+
+    /// Sets the sampler for the spans whose parents are remote and sampled.
+    pub fn remote_parent_sampled<S>(mut self, sampler: S) -> Self
+    where
+        S: Sampler<T> + Send + Sync + 'static,
+    {
+        self.remote_parent_sampled = sampler.boxed();
+        self
+    }
+
+    /// Sets the sampler for the spans whose parents are remote and not sampled.
+    pub fn remote_parent_not_sampled<S>(mut self, sampler: S) -> Self
+    where
+        S: Sampler<T> + Send + Sync + 'static,
+    {
+        self.remote_parent_not_sampled = sampler.boxed();
+        self
+    }
+
+    /// Sets the sampler for the spans whose parents are local and sampled.
+    pub fn local_parent_sampled<S>(mut self, sampler: S) -> Self
+    where
+        S: Sampler<T> + Send + Sync + 'static,
+    {
+        self.local_parent_sampled = sampler.boxed();
+        self
+    }
+
+    /// Sets the sampler for the spans whose parents are local and not sampled.
+    pub fn local_parent_not_sampled<S>(mut self, sampler: S) -> Self
+    where
+        S: Sampler<T> + Send + Sync + 'static,
+    {
+        self.local_parent_not_sampled = sampler.boxed();
+        self
+    }
+}
+impl<T: ParentState> Sampler<T> for ParentBasedSampler<T> {
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        let sampler = match span.parent() {
+            None => &self.root,
+            Some(p) if p.is_deferred() => &self.root,
+            Some(p) => match (p.is_remote(), p.is_sampled()) {
+                (true, true) => &self.remote_parent_sampled,
+                (true, false) => &self.remote_parent_not_sampled,
+                (false, true) => &self.local_parent_sampled,
+                (false, false) => &self.local_parent_not_sampled,
+            },
+        };
+        sampler.is_sampled(span)
+    }
+}
+impl<T> fmt::Debug for ParentBasedSampler<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ParentBasedSampler {{ .. }}")
+    }
+}
+
+/// Clock used by samplers which depend on time.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+}
+impl<F: Fn() -> Instant> Clock for F {
+    fn now(&self) -> Instant {
+        self()
+    }
+}
+
+/// `Clock` which returns `Instant::now()`.
+#[derive(Debug, Default, Clone)]
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// This samples traces so that the throughput of each operation approaches a target.
+///
+/// The sampler keeps a sampling probability for each operation and counts the sampled traces.
+/// Every `recompute_interval`, the probabilities are recomputed from the observed throughputs
+/// (at most doubling at once to avoid oscillations).
+/// Operations which have no requests during an interval are evicted to make room for others.
+/// In addition, each operation is sampled at least `lower_bound` traces per second
+/// regardless of its probability, so rare operations are not missed.
+///
+/// Since this is meant to decide whether to start new traces,
+/// it is usually used as the root sampler of `ParentBasedSampler`.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::sampler::{AdaptiveSampler, ParentBasedSampler};
+/// use rustracing::state::SpanContextState;
+/// use rustracing::Tracer;
+///
+/// // Samples about 10 traces per second (and at least 0.1 traces per second) for each operation.
+/// let sampler = AdaptiveSampler::builder(10.0).lower_bound(0.1).build().unwrap();
+/// let (tracer, _span_rx) = Tracer::<_, SpanContextState>::new(ParentBasedSampler::new(sampler));
+///
+/// // The first trace of an operation is always sampled because of the lower bound.
+/// assert!(tracer.span("foo").start().is_sampled());
+/// ```
+pub struct AdaptiveSampler<C = SystemClock> {
+    target_tps: f64,
+    lower_bound_interval: Option<Duration>,
+    initial_probability: f64,
+    recompute_interval: Duration,
+    max_operations: usize,
+    clock: C,
+    state: Mutex<AdaptiveState>,
+}
+impl AdaptiveSampler {
+    /// The default value of the initial sampling probability of an operation.
+    pub const DEFAULT_INITIAL_PROBABILITY: f64 = 0.001;
+
+    /// The default value of the lower-bound throughput (one trace per minute).
+    pub const DEFAULT_LOWER_BOUND: f64 = 1.0 / 60.0;
+
+    /// The default value of the interval of recomputing the probabilities.
+    pub const DEFAULT_RECOMPUTE_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// The default value of the maximum number of tracked operations.
+    pub const DEFAULT_MAX_OPERATIONS: usize = 2000;
+
+    /// The minimum sampling probability.
+    ///
+    /// Probabilities never reach zero so that they can be increased again.
+    pub const MIN_PROBABILITY: f64 = 1e-6;
+
+    /// Returns a builder which makes an `AdaptiveSampler`
+    /// whose target throughput is `target_tps` traces per second for each operation.
+    pub fn builder(target_tps: f64) -> AdaptiveSamplerBuilder {
+        AdaptiveSamplerBuilder {
+            target_tps,
+            lower_bound: Self::DEFAULT_LOWER_BOUND,
+            initial_probability: Self::DEFAULT_INITIAL_PROBABILITY,
+            recompute_interval: Self::DEFAULT_RECOMPUTE_INTERVAL,
+            max_operations: Self::DEFAULT_MAX_OPERATIONS,
+            clock: SystemClock,
+        }
+    }
+}
+impl<C: Clock> AdaptiveSampler<C> {
+    /// Returns the current sampling probability of the operation named `operation_name`.
+    ///
+    /// The initial probability is returned for the operations which have not been tracked.
+    pub fn probability(&self, operation_name: &str) -> f64 {
+        self.state
+            .lock()
+            .ok()
+            .and_then(|state| {
+                state
+                    .operations
+                    .get(operation_name)
+                    .map(|op| op.probability)
+            })
+            .unwrap_or(self.initial_probability)
+    }
+
+    /// Returns the current sampling probabilities of the tracked operations.
+    pub fn probabilities(&self) -> HashMap<String, f64> {
+        self.state.lock().map_or_else(
+            |_| HashMap::new(),
+            |state| {
+                state
+                    .operations
+                    .iter()
+                    .map(|(name, op)| (name.clone(), op.probability))
+                    .collect()
+            },
+        )
+    }
+
+    fn decide(&self, operation_name: &str) -> bool {
+        let now = self.clock.now();
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+        let elapsed = now.saturating_duration_since(state.last_recomputed);
+        if elapsed >= self.recompute_interval {
+            self.recompute(&mut state, elapsed);
+            state.last_recomputed = now;
+        }
+
+        let initial_probability = self.initial_probability;
+        let tracked_operations = state.operations.len();
+        let op = match state.operations.get_mut(operation_name) {
+            Some(op) => op,
+            None if tracked_operations < self.max_operations => state
+                .operations
+                .entry(operation_name.to_owned())
+                .or_insert_with(|| OperationState::new(initial_probability)),
+            None => {
+                return rand::thread_rng().gen_range(0.0..1.0) < initial_probability;
+            }
+        };
+        op.requests += 1;
+        let sampled = rand::thread_rng().gen_range(0.0..1.0) < op.probability
+            || self.lower_bound_interval.is_some_and(|interval| {
+                op.lower_bound_sampled_at
+                    .is_none_or(|t| now.saturating_duration_since(t) >= interval)
+            });
+        if sampled {
+            op.sampled += 1;
+            op.lower_bound_sampled_at = Some(now);
+        }
+        sampled
+    }
+
+    fn recompute(&self, state: &mut AdaptiveState, elapsed: Duration) {
+        let elapsed = elapsed.as_secs_f64();
+        state.operations.retain(|_, op| op.requests != 0);
+        for op in state.operations.values_mut() {
+            let max_probability = (op.probability * 2.0).min(1.0);
+            let probability = if op.sampled == 0 {
+                max_probability
+            } else {
+                let observed_tps = op.sampled as f64 / elapsed;
+                (op.probability * self.target_tps / observed_tps).min(max_probability)
+            };
+            op.probability = probability.max(AdaptiveSampler::MIN_PROBABILITY);
+            op.requests = 0;
+            op.sampled = 0;
+        }
+    }
+}
+impl<T, C: Clock> Sampler<T> for AdaptiveSampler<C> {
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        self.decide(span.operation_name())
+    }
+}
+impl<C> fmt::Debug for AdaptiveSampler<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AdaptiveSampler")
+            .field("target_tps", &self.target_tps)
+            .field("lower_bound_interval", &self.lower_bound_interval)
+            .field("initial_probability", &self.initial_probability)
+            .field("recompute_interval", &self.recompute_interval)
+            .field("max_operations", &self.max_operations)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+struct AdaptiveState {
+    operations: HashMap<String, OperationState>,
+    last_recomputed: Instant,
+}
+
+#[derive(Debug)]
+struct OperationState {
+    probability: f64,
+    requests: u64,
+    sampled: u64,
+    lower_bound_sampled_at: Option<Instant>,
+}
+impl OperationState {
+    fn new(probability: f64) -> Self {
+        OperationState {
+            probability,
+            requests: 0,
+            sampled: 0,
+            lower_bound_sampled_at: None,
+        }
+    }
+}
+
+/// `AdaptiveSampler` builder.
+#[derive(Debug, Clone)]
+pub struct AdaptiveSamplerBuilder<C = SystemClock> {
+    target_tps: f64,
+    lower_bound: f64,
+    initial_probability: f64,
+    recompute_interval: Duration,
+    max_operations: usize,
+    clock: C,
+}
+impl<C: Clock> AdaptiveSamplerBuilder<C> {
+    /// Sets the lower-bound throughput (traces per second) of each operation.
+    ///
+    /// `0.0` disables the lower bound.
+    /// The default value is `AdaptiveSampler::DEFAULT_LOWER_BOUND`.
+    pub fn lower_bound(mut self, tps: f64) -> Self {
+        self.lower_bound = tps;
+        self
+    }
+
+    /// Sets the initial sampling probability of an operation.
+    ///
+    /// The default value is `AdaptiveSampler::DEFAULT_INITIAL_PROBABILITY`.
+    pub fn initial_probability(mut self, probability: f64) -> Self {
+        self.initial_probability = probability;
+        self
+    }
+
+    /// Sets the interval of recomputing the probabilities.
+    ///
+    /// The default value is `AdaptiveSampler::DEFAULT_RECOMPUTE_INTERVAL`.
+    pub fn recompute_interval(mut self, interval: Duration) -> Self {
+        self.recompute_interval = interval;
+        self
+    }
+
+    /// Sets the maximum number of tracked operations.
+    ///
+    /// The operations beyond the limit are sampled with the initial probability.
+    /// The default value is `AdaptiveSampler::DEFAULT_MAX_OPERATIONS`.
+    pub fn max_operations(mut self, max: usize) -> Self {
+        self.max_operations = max;
+        self
+    }
+
+    /// Sets the clock used by the sampler.
+    ///
+    /// The default clock is `SystemClock`.
+    pub fn clock<D: Clock>(self, clock: D) -> AdaptiveSamplerBuilder<D> {
+        AdaptiveSamplerBuilder {
+            target_tps: self.target_tps,
+            lower_bound: self.lower_bound,
+            initial_probability: self.initial_probability,
+            recompute_interval: self.recompute_interval,
+            max_operations: self.max_operations,
+            clock,
+        }
+    }
+
+    /// Builds an `AdaptiveSampler` instance.
+    ///
+    /// # Errors
+    ///
+    /// If the target throughput is not a positive finite number,
+    /// the lower bound is not a non-negative finite number,
+    /// the initial probability is not in the range `0.0...1.0`,
+    /// the recompute interval is zero
+    /// or the lower bound is too small to be represented as an interval,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn build(self) -> Result<AdaptiveSampler<C>> {
+        track_assert!(0.0 < self.target_tps, ErrorKind::InvalidInput);
+        track_assert!(self.target_tps.is_finite(), ErrorKind::InvalidInput);
+        track_assert!(0.0 <= self.lower_bound, ErrorKind::InvalidInput);
+        track_assert!(self.lower_bound.is_finite(), ErrorKind::InvalidInput);
+        track_assert!(0.0 <= self.initial_probability, ErrorKind::InvalidInput);
+        track_assert!(self.initial_probability <= 1.0, ErrorKind::InvalidInput);
+        track_assert_ne!(
+            self.recompute_interval,
+            Duration::ZERO,
+            ErrorKind::InvalidInput
+        );
+
+        let lower_bound_interval = if self.lower_bound == 0.0 {
+            None
+        } else {
+            let interval = Duration::try_from_secs_f64(1.0 / self.lower_bound)
+                .map_err(|e| track!(Error::from(ErrorKind::InvalidInput.cause(e))))?;
+            Some(interval)
+        };
+        let now = self.clock.now();
+        Ok(AdaptiveSampler {
+            target_tps: self.target_tps,
+            lower_bound_interval,
+            initial_probability: self.initial_probability,
+            recompute_interval: self.recompute_interval,
+            max_operations: self.max_operations,
+            clock: self.clock,
+            state: Mutex::new(AdaptiveState {
+                operations: HashMap::new(),
+                last_recomputed: now,
+            }),
+        })
+    }
+}
+
 /// This samples traces which have one or more references.
 #[derive(Debug, Clone)]
 pub struct PassiveSampler;
@@ -125,3 +896,220 @@ where
         self.0.is_sampled(span) && self.1.is_sampled(span)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::{Span, SpanContext};
+    use crate::state::{SpanContextState, TraceId, FLAG_SAMPLED};
+    use crate::Tracer;
+    use std::sync::Arc;
+
+    #[test]
+    fn rate_limiting_sampler_works() {
+        assert!(RateLimitingSampler::new(0.0, 1).is_err());
+        assert!(RateLimitingSampler::new(f64::INFINITY, 1).is_err());
+        assert!(RateLimitingSampler::new(1.0, 0).is_err());
+
+        let sampler = RateLimitingSampler::new(0.001, 3).unwrap();
+        assert!(sampler.try_acquire());
+        assert!(sampler.try_acquire());
+        assert!(sampler.try_acquire());
+        assert!(!sampler.try_acquire());
+
+        let sampler = RateLimitingSampler::new(1_000_000_000.0, 1).unwrap();
+        let start = Instant::now();
+        while start.elapsed().as_micros() < 100 {}
+        assert!(sampler.try_acquire());
+    }
+
+    #[test]
+    fn glob_match_works() {
+        assert!(glob_match("/api/*", "/api/"));
+        assert!(glob_match("/api/*", "/api/users/1"));
+        assert!(glob_match("*/health", "GET /health"));
+        assert!(glob_match("/v?/*/items", "/v1/users/items"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("/api/*", "/apiv2"));
+        assert!(!glob_match("/v?", "/v10"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn per_operation_sampler_works() {
+        let sampler = PerOperationSampler::new(AllSampler)
+            .operation("/health", NullSampler)
+            .pattern("/internal/*", NullSampler)
+            .pattern("/internal/debug", AllSampler)
+            .max_operations(3);
+        let (tracer, _span_rx) = Tracer::new(sampler);
+        let is_sampled = |name| tracer.span(name).start_with_state(()).is_sampled();
+        assert!(!is_sampled("/health"));
+        assert!(is_sampled("/checkout"));
+        assert!(!is_sampled("/internal/a"));
+        assert!(!is_sampled("/internal/debug"));
+
+        // Reached the limit
+        assert!(!is_sampled("/internal/b"));
+        assert!(is_sampled("/other"));
+        assert!(!is_sampled("/internal/a"));
+
+        let sampler = PerOperationSampler::<()>::new(AllSampler)
+            .pattern("/internal/*", NullSampler)
+            .max_operations(1);
+        assert_eq!(sampler.match_patterns("/internal/a"), Some(0));
+        assert_eq!(sampler.match_patterns("/internal/b"), Some(0));
+        assert_eq!(sampler.match_patterns("/other"), None);
+        assert_eq!(sampler.tracked_operations(), 1);
+    }
+
+    #[test]
+    fn trace_id_ratio_sampler_works() {
+        assert!(TraceIdRatioSampler::new(-0.1).is_err());
+        assert!(TraceIdRatioSampler::new(1.1).is_err());
+
+        let id = |low| TraceId { high: 0, low };
+        let sampler = TraceIdRatioSampler::new(0.5).unwrap();
+        assert!(sampler.is_sampled_trace(id(0)));
+        assert!(sampler.is_sampled_trace(id(u64::MAX / 2)));
+        assert!(!sampler.is_sampled_trace(id(u64::MAX / 2 + 1)));
+
+        let all = TraceIdRatioSampler::new(1.0).unwrap();
+        let none = TraceIdRatioSampler::new(0.0).unwrap();
+        assert!(all.is_sampled_trace(id(u64::MAX)));
+        assert!(!none.is_sampled_trace(id(0)));
+
+        // Roots use the pre-generated trace identifiers.
+        let sampler =
+            Sampler::<SpanContextState>::or(TraceIdRatioSampler::new(0.5).unwrap(), NullSampler);
+        let (tracer, _span_rx) = Tracer::<_, SpanContextState>::new(sampler);
+        let sampled = (0..100)
+            .filter_map(|_| {
+                let span: Span<_> = tracer.span("foo").start();
+                span.context().map(|c| c.state().trace_id())
+            })
+            .collect::<Vec<_>>();
+        assert!(!sampled.is_empty());
+        let sampler = TraceIdRatioSampler::new(0.5).unwrap();
+        assert!(sampled.into_iter().all(|id| sampler.is_sampled_trace(id)));
+    }
+
+    #[test]
+    fn adaptive_sampler_works() {
+        assert!(AdaptiveSampler::builder(0.0).build().is_err());
+        assert!(AdaptiveSampler::builder(1.0)
+            .lower_bound(-1.0)
+            .build()
+            .is_err());
+        assert!(AdaptiveSampler::builder(1.0)
+            .initial_probability(1.5)
+            .build()
+            .is_err());
+        assert!(AdaptiveSampler::builder(1.0)
+            .lower_bound(1e-20)
+            .build()
+            .is_err());
+
+        let start = Instant::now();
+        let offset = Arc::new(Mutex::new(Duration::ZERO));
+        let offset_for_clock = Arc::clone(&offset);
+        let sampler = AdaptiveSampler::builder(1.0)
+            .initial_probability(1.0)
+            .lower_bound(0.0)
+            .recompute_interval(Duration::from_secs(10))
+            .max_operations(2)
+            .clock(move || start + *offset_for_clock.lock().unwrap())
+            .build()
+            .unwrap();
+        let advance = |secs| *offset.lock().unwrap() += Duration::from_secs(secs);
+
+        // 100 traces in 10 seconds (the target is 10 traces)
+        for _ in 0..100 {
+            assert!(sampler.decide("busy"));
+        }
+        assert!(sampler.decide("rare"));
+        assert!(sampler.decide("untracked"));
+        assert_eq!(sampler.probabilities().len(), 2);
+        advance(10);
+        sampler.decide("busy");
+        assert!((sampler.probability("busy") - 0.1).abs() < 1e-9);
+
+        // "rare" achieved only 1 trace, but the probability is already 1.0
+        assert_eq!(sampler.probability("rare"), 1.0);
+        assert_eq!(sampler.probability("untracked"), 1.0);
+
+        // No traces of "busy" are sampled: the probability is doubled
+        let offset_for_clock = Arc::clone(&offset);
+        let sampler = AdaptiveSampler::builder(1.0)
+            .initial_probability(0.0)
+            .lower_bound(0.0)
+            .recompute_interval(Duration::from_secs(10))
+            .clock(move || start + *offset_for_clock.lock().unwrap())
+            .build()
+            .unwrap();
+        assert!(!sampler.decide("busy"));
+        advance(10);
+        sampler.decide("busy");
+        assert_eq!(
+            sampler.probability("busy"),
+            AdaptiveSampler::MIN_PROBABILITY
+        );
+        advance(10);
+        sampler.decide("idle");
+        assert_eq!(
+            sampler.probability("busy"),
+            AdaptiveSampler::MIN_PROBABILITY * 2.0
+        );
+
+        // Operations without requests during an interval are evicted
+        advance(10);
+        sampler.decide("idle");
+        assert!(!sampler.probabilities().contains_key("busy"));
+        assert!(sampler.probabilities().contains_key("idle"));
+    }
+
+    #[test]
+    fn adaptive_sampler_lower_bound_works() {
+        let start = Instant::now();
+        let offset = Arc::new(Mutex::new(Duration::ZERO));
+        let offset_for_clock = Arc::clone(&offset);
+        let sampler = AdaptiveSampler::builder(1.0)
+            .initial_probability(0.0)
+            .lower_bound(0.5)
+            .clock(move || start + *offset_for_clock.lock().unwrap())
+            .build()
+            .unwrap();
+        let advance = |secs| *offset.lock().unwrap() += Duration::from_secs(secs);
+
+        assert!(sampler.decide("rare"));
+        assert!(!sampler.decide("rare"));
+        advance(1);
+        assert!(!sampler.decide("rare"));
+        advance(1);
+        assert!(sampler.decide("rare"));
+    }
+
+    #[test]
+    fn parent_based_sampler_works() {
+        let sampler = ParentBasedSampler::new(NullSampler)
+            .remote_parent_sampled(NullSampler)
+            .remote_parent_not_sampled(AllSampler);
+        let (tracer, _span_rx) = Tracer::<_, SpanContextState>::new(sampler);
+        let is_sampled = |parent: Option<SpanContextState>| {
+            let parent = parent.map(|state| SpanContext::new(state, Vec::new()));
+            let span: Span<_> = tracer.span("foo").child_of(&parent).start();
+            span.is_sampled()
+        };
+        let state = SpanContextState::new(TraceId::new(), 1);
+        assert!(!is_sampled(None));
+        assert!(!is_sampled(Some(
+            state.clone().with_remote(true).with_flags(FLAG_SAMPLED)
+        )));
+        assert!(is_sampled(Some(state.clone().with_remote(true))));
+        assert!(is_sampled(Some(state.clone().with_flags(FLAG_SAMPLED))));
+        assert!(!is_sampled(Some(state.clone())));
+        assert!(!is_sampled(Some(
+            state.with_remote(true).with_deferred(true)
+        )));
+    }
+}
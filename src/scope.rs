@@ -0,0 +1,150 @@
+//! Thread-local active span management.
+//!
+//! This module corresponds to the `ScopeManager` concept of OpenTracing.
+//! A `SpanHandle` can be activated for the current thread,
+//! then it is available via `active()` without passing it through function arguments.
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::sampler::AllSampler;
+//! use rustracing::scope;
+//! use rustracing::Tracer;
+//!
+//! fn do_something(tracer: &Tracer<AllSampler, ()>) {
+//!     // Starts a child span of the active one.
+//!     let _span = tracer.scoped_span("child").start_with_state(());
+//! }
+//!
+//! let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+//! let tracer = Tracer::with_sender(AllSampler, span_tx);
+//! {
+//!     let span = tracer.span("parent").start_with_state(());
+//!     let _scope = scope::activate(span.handle());
+//!     do_something(&tracer);
+//! }
+//! assert!(scope::active::<()>().is_none());
+//!
+//! let child = span_rx.try_recv().unwrap();
+//! assert_eq!(child.operation_name(), "child");
+//! assert!(child.references()[0].is_child_of());
+//! ```
+use crate::span::SpanHandle;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+thread_local! {
+    static ACTIVE_SPANS: RefCell<HashMap<TypeId, Box<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// Activates `handle` for the current thread.
+///
+/// The span is active until the returned `Scope` is dropped,
+/// then the span which was active before this call is re-activated.
+///
+/// Spans are managed separately for each type of span context state `T`.
+pub fn activate<T: 'static>(handle: SpanHandle<T>) -> Scope<T> {
+    let previous = ACTIVE_SPANS.with(|spans| {
+        spans
+            .borrow_mut()
+            .insert(TypeId::of::<T>(), Box::new(handle))
+    });
+    Scope {
+        previous,
+        _state: PhantomData,
+    }
+}
+
+/// Returns the handle of the span active for the current thread.
+pub fn active<T: Clone + 'static>() -> Option<SpanHandle<T>> {
+    ACTIVE_SPANS.with(|spans| {
+        spans
+            .borrow()
+            .get(&TypeId::of::<T>())
+            .and_then(|handle| handle.downcast_ref::<SpanHandle<T>>())
+            .cloned()
+    })
+}
+
+/// RAII guard which keeps a span active for the current thread.
+///
+/// When this is dropped, the previously active span is re-activated.
+/// Scopes should be dropped in the reverse order of their creation.
+///
+/// Since this is bound to a thread, it is neither `Send` nor `Sync`.
+#[must_use = "the span is deactivated when the scope is dropped"]
+#[derive(Debug)]
+pub struct Scope<T: 'static> {
+    previous: Option<Box<dyn Any>>,
+    _state: PhantomData<*const T>,
+}
+impl<T: 'static> Drop for Scope<T> {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        let _ = ACTIVE_SPANS.try_with(|spans| {
+            let mut spans = spans.borrow_mut();
+            if let Some(previous) = previous {
+                spans.insert(TypeId::of::<T>(), previous);
+            } else {
+                spans.remove(&TypeId::of::<T>());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::Tracer;
+    use std::thread;
+
+    fn span_name(handle: Option<SpanHandle<&'static str>>) -> Option<&'static str> {
+        handle.and_then(|h| h.context().map(|c| *c.state()))
+    }
+
+    #[test]
+    fn activate_works() {
+        let (tracer, _span_rx) = Tracer::new(AllSampler);
+        let outer = tracer.span("outer").start_with_state("outer");
+        let inner = tracer.span("inner").start_with_state("inner");
+        assert!(active::<&str>().is_none());
+        {
+            let _outer_scope = activate(outer.handle());
+            assert_eq!(span_name(active()), Some("outer"));
+            {
+                let _inner_scope = activate(inner.handle());
+                assert_eq!(span_name(active()), Some("inner"));
+                assert!(active::<()>().is_none());
+
+                thread::spawn(|| assert!(active::<&str>().is_none()))
+                    .join()
+                    .unwrap();
+            }
+            assert_eq!(span_name(active()), Some("outer"));
+        }
+        assert!(active::<&str>().is_none());
+    }
+
+    #[test]
+    fn scoped_span_works() {
+        let (tracer, span_rx) = Tracer::new(AllSampler);
+        {
+            let root = tracer.scoped_span("root").start_with_state(1);
+            let _scope = activate(root.handle());
+            let _child = tracer.scoped_span("child").start_with_state(2);
+            let _ = tracer.scoped_span("sibling").start_with_state(3);
+        }
+        let sibling = span_rx.try_recv().unwrap();
+        assert!(sibling.references()[0].is_child_of());
+        assert_eq!(*sibling.references()[0].span(), 1);
+
+        let child = span_rx.try_recv().unwrap();
+        assert_eq!(*child.references()[0].span(), 1);
+
+        let root = span_rx.try_recv().unwrap();
+        assert!(root.references().is_empty());
+    }
+}
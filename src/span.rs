@@ -1,12 +1,18 @@
 //! Span.
 use crate::carrier;
 use crate::convert::MaybeAsRef;
-use crate::log::{Log, LogBuilder, StdErrorLogFieldsBuilder};
-use crate::sampler::{AllSampler, Sampler};
+use crate::log::{AsDynError, Log, LogBuilder, StdErrorLogFieldsBuilder};
+use crate::sampler::{AllSampler, Sampler, TraceIdState};
+use crate::state::TraceId;
 use crate::tag::{StdTag, Tag, TagValue};
-use crate::Result;
+use crate::tracer::SpanSink;
+use crate::{Error, ErrorKind, Result};
 use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::fmt;
 use std::io::{Read, Write};
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::SystemTime;
 
 /// Finished span receiver.
@@ -45,7 +51,7 @@ impl<T> Span<T> {
         SpanHandle(
             self.0
                 .as_ref()
-                .map(|inner| (inner.context.clone(), inner.span_tx.clone())),
+                .map(|inner| (inner.context.clone(), Arc::clone(&inner.sink))),
         )
     }
 
@@ -107,8 +113,7 @@ impl<T> Span<T> {
     {
         if let Some(inner) = self.0.as_mut() {
             for tag in f() {
-                inner.tags.retain(|x| x.name() != tag.name());
-                inner.tags.push(tag);
+                inner.push_tag(tag);
             }
         }
     }
@@ -120,8 +125,14 @@ impl<T> Span<T> {
     {
         if let Some(inner) = self.0.as_mut() {
             let item = f();
-            inner.context.baggage_items.retain(|x| x.name != item.name);
-            inner.context.baggage_items.push(item);
+            let items = &mut inner.context.baggage_items;
+            if let Some(i) = items.iter().position(|x| x.name == item.name) {
+                items.remove(i);
+            } else if items.len() >= inner.sink.span_limits().max_baggage_items {
+                inner.dropped.baggage_items += 1;
+                return;
+            }
+            items.push(item);
         }
     }
 
@@ -142,9 +153,7 @@ impl<T> Span<T> {
         if let Some(inner) = self.0.as_mut() {
             let mut builder = LogBuilder::new();
             f(&mut builder);
-            if let Some(log) = builder.finish() {
-                inner.logs.push(log);
-            }
+            inner.push_log(builder);
         }
     }
 
@@ -152,6 +161,9 @@ impl<T> Span<T> {
     ///
     /// This is a simple wrapper of `log` method
     /// except that the `StdTag::error()` tag will be set in this method.
+    ///
+    /// Unless the status of this span is already `SpanStatus::Error`,
+    /// it is set to `SpanStatus::Error` described by the `message` field of the log.
     pub fn error_log<F>(&mut self, f: F)
     where
         F: FnOnce(&mut StdErrorLogFieldsBuilder),
@@ -159,15 +171,66 @@ impl<T> Span<T> {
         if let Some(inner) = self.0.as_mut() {
             let mut builder = LogBuilder::new();
             f(&mut builder.error());
-            if let Some(log) = builder.finish() {
-                inner.logs.push(log);
-            }
-            if !inner.tags.iter().any(|x| x.name() == "error") {
-                inner.tags.push(StdTag::error());
+            let description = builder.message().unwrap_or_default();
+            inner.push_log(builder);
+            if !inner.status.is_error() {
+                inner.set_status(SpanStatus::error(description));
             }
         }
     }
 
+    /// Logs `error` and the chain of its sources as an error log.
+    ///
+    /// The `error.kind` field is set to the type name of `error` (see `AsDynError::type_name()`).
+    /// See `StdErrorLogFieldsBuilder::error_chain()` for the other fields.
+    pub fn log_error<E>(&mut self, error: &E)
+    where
+        E: ?Sized + AsDynError,
+    {
+        self.error_log(|log| {
+            log.kind(error.type_name())
+                .error_chain(error.as_dyn_error());
+        });
+    }
+
+    /// Sets the kind of this span.
+    ///
+    /// This also sets the `StdTag::span_kind(kind)` tag.
+    pub fn set_kind(&mut self, kind: SpanKind) {
+        self.set_tag(|| StdTag::span_kind(kind));
+    }
+
+    /// Sets the status of this span.
+    ///
+    /// For compatibility with OpenTracing, `SpanStatus::Error` sets the `StdTag::error()` tag
+    /// and the other statuses remove it.
+    /// Conversely, setting the `error` tag by `set_tag` also updates the status.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustracing::Tracer;
+    /// use rustracing::sampler::AllSampler;
+    /// use rustracing::span::{SpanKind, SpanStatus};
+    ///
+    /// let (tracer, span_rx) = Tracer::new(AllSampler);
+    /// {
+    ///     let mut span = tracer.span("foo").kind(SpanKind::Client).start_with_state(());
+    ///     span.set_status(SpanStatus::error("connection refused"));
+    /// }
+    /// let span = span_rx.try_recv().unwrap();
+    /// assert_eq!(span.kind(), Some(SpanKind::Client));
+    /// assert_eq!(span.status(), &SpanStatus::error("connection refused"));
+    ///
+    /// let tags = span.tags().iter().map(|t| t.name()).collect::<Vec<_>>();
+    /// assert_eq!(tags, ["span.kind", "error"]);
+    /// ```
+    pub fn set_status(&mut self, status: SpanStatus) {
+        if let Some(inner) = self.0.as_mut() {
+            inner.set_status(status);
+        }
+    }
+
     /// Starts a `ChildOf` span if this span is sampled.
     pub fn child<N, F>(&self, operation_name: N, f: F) -> Span<T>
     where
@@ -188,6 +251,73 @@ impl<T> Span<T> {
         self.handle().follower(operation_name, f)
     }
 
+    /// Finishes this span and sends it to the associated `SpanReceiver`.
+    ///
+    /// This is equivalent to dropping the span, except that the result is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustracing::Tracer;
+    /// use rustracing::sampler::{AllSampler, NullSampler};
+    /// use rustracing::span::FinishStatus;
+    ///
+    /// let (span_tx, _span_rx) = crossbeam_channel::bounded(1);
+    /// let tracer = Tracer::with_sender(AllSampler, span_tx);
+    /// assert_eq!(tracer.span("foo").start_with_state(()).finish(), FinishStatus::Sent);
+    /// assert_eq!(tracer.span("bar").start_with_state(()).finish(), FinishStatus::Dropped);
+    ///
+    /// let tracer = tracer.clone_with_sampler(NullSampler);
+    /// assert_eq!(tracer.span("baz").start_with_state(()).finish(), FinishStatus::Unsampled);
+    /// ```
+    pub fn finish(mut self) -> FinishStatus {
+        self.finish_inner()
+    }
+
+    /// Finishes this span at `time` and sends it to the associated `SpanReceiver`.
+    ///
+    /// See `finish()` for details.
+    pub fn finish_at(mut self, time: SystemTime) -> FinishStatus {
+        self.set_finish_time(|| time);
+        self.finish_inner()
+    }
+
+    fn log_panic(&mut self) {
+        let details = crate::panic::last_panic();
+        self.error_log(|log| {
+            log.kind("panic");
+            if let Some(details) = details {
+                log.message(details.message);
+                #[cfg(feature = "stacktrace")]
+                log.stack_trace(details.stack);
+            }
+        });
+    }
+
+    fn finish_inner(&mut self) -> FinishStatus {
+        if let Some(inner) = self.0.take() {
+            let finished = FinishedSpan {
+                operation_name: inner.operation_name,
+                start_time: inner.start_time,
+                finish_time: inner.finish_time.unwrap_or_else(SystemTime::now),
+                references: inner.references,
+                tags: inner.tags,
+                logs: inner.logs,
+                context: inner.context,
+                kind: inner.kind,
+                status: inner.status,
+                dropped: inner.dropped,
+            };
+            if inner.sink.send(finished) {
+                FinishStatus::Sent
+            } else {
+                FinishStatus::Dropped
+            }
+        } else {
+            FinishStatus::Unsampled
+        }
+    }
+
     pub(crate) fn new(
         operation_name: Cow<'static, str>,
         start_time: SystemTime,
@@ -195,36 +325,41 @@ impl<T> Span<T> {
         tags: Vec<Tag>,
         state: T,
         baggage_items: Vec<BaggageItem>,
-        span_tx: SpanSender<T>,
+        sink: Arc<SpanSink<T>>,
     ) -> Self {
-        let context = SpanContext::new(state, baggage_items);
-        let inner = SpanInner {
+        let mut context = SpanContext::new(state, baggage_items);
+        let mut dropped = DroppedCounts::default();
+        let limits = sink.span_limits();
+        if context.baggage_items.len() > limits.max_baggage_items {
+            dropped.baggage_items = context.baggage_items.len() - limits.max_baggage_items;
+            context.baggage_items.truncate(limits.max_baggage_items);
+        }
+        let mut inner = SpanInner {
             operation_name,
             start_time,
             finish_time: None,
             references,
-            tags,
+            tags: Vec::with_capacity(tags.len()),
             logs: Vec::new(),
             context,
-            span_tx,
+            sink,
+            kind: None,
+            status: SpanStatus::Unset,
+            dropped,
+            _panic_guard: crate::panic::SpanGuard::new(),
         };
+        for tag in tags {
+            inner.push_tag(tag);
+        }
         Span(Some(inner))
     }
 }
 impl<T> Drop for Span<T> {
     fn drop(&mut self) {
-        if let Some(inner) = self.0.take() {
-            let finished = FinishedSpan {
-                operation_name: inner.operation_name,
-                start_time: inner.start_time,
-                finish_time: inner.finish_time.unwrap_or_else(SystemTime::now),
-                references: inner.references,
-                tags: inner.tags,
-                logs: inner.logs,
-                context: inner.context,
-            };
-            let _ = inner.span_tx.try_send(finished);
+        if std::thread::panicking() {
+            self.log_panic();
         }
+        self.finish_inner();
     }
 }
 impl<T> MaybeAsRef<SpanContext<T>> for Span<T> {
@@ -233,6 +368,222 @@ impl<T> MaybeAsRef<SpanContext<T>> for Span<T> {
     }
 }
 
+/// Kind of span.
+///
+/// It describes the relationship between the span, its parents, and its children in a trace,
+/// and it is reported as the `"span.kind"` tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpanKind {
+    /// The client side of an RPC (`"client"`).
+    Client,
+
+    /// The server side of an RPC (`"server"`).
+    Server,
+
+    /// The producer of a message (`"producer"`).
+    Producer,
+
+    /// The consumer of a message (`"consumer"`).
+    Consumer,
+
+    /// An internal operation (`"internal"`).
+    ///
+    /// Note that this is not defined in the OpenTracing semantic conventions.
+    Internal,
+}
+impl SpanKind {
+    /// Returns the `"span.kind"` tag value of this kind.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SpanKind::Client => "client",
+            SpanKind::Server => "server",
+            SpanKind::Producer => "producer",
+            SpanKind::Consumer => "consumer",
+            SpanKind::Internal => "internal",
+        }
+    }
+}
+impl fmt::Display for SpanKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+impl FromStr for SpanKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "client" => Ok(SpanKind::Client),
+            "server" => Ok(SpanKind::Server),
+            "producer" => Ok(SpanKind::Producer),
+            "consumer" => Ok(SpanKind::Consumer),
+            "internal" => Ok(SpanKind::Internal),
+            _ => track_panic!(ErrorKind::InvalidInput, "Unknown span kind: {:?}", s),
+        }
+    }
+}
+impl From<SpanKind> for Cow<'static, str> {
+    fn from(f: SpanKind) -> Self {
+        Cow::Borrowed(f.as_str())
+    }
+}
+
+/// Status of span.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub enum SpanStatus {
+    /// The status is not set (the default).
+    #[default]
+    Unset,
+
+    /// The operation has completed successfully.
+    Ok,
+
+    /// The operation has failed.
+    Error {
+        /// The description of the error.
+        description: Cow<'static, str>,
+    },
+}
+impl SpanStatus {
+    /// Makes a `SpanStatus::Error` instance with `description`.
+    pub fn error<D>(description: D) -> Self
+    where
+        D: Into<Cow<'static, str>>,
+    {
+        SpanStatus::Error {
+            description: description.into(),
+        }
+    }
+
+    /// Returns `true` if this is `SpanStatus::Error`.
+    pub fn is_error(&self) -> bool {
+        matches!(*self, SpanStatus::Error { .. })
+    }
+}
+
+/// The result of finishing a span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FinishStatus {
+    /// The span has been sent to the `SpanReceiver`.
+    Sent,
+
+    /// The span has been dropped because it could not be sent to the `SpanReceiver`
+    /// (e.g., the channel is full).
+    Dropped,
+
+    /// The span is not sampled, so there is nothing to send.
+    Unsampled,
+}
+
+/// Limits on the amount of data recorded in a span.
+///
+/// The data exceeding the limits are dropped (or truncated)
+/// and the number of them can be retrieved from `FinishedSpan`.
+///
+/// The limits are opt-in: `SpanLimits::default()` (i.e., the limits of tracers unless
+/// `TracerBuilder::span_limits()` is specified) has no limits,
+/// and `SpanLimits::recommended()` provides reasonable ones.
+/// The `error` tag is always recorded regardless of `max_tags`.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::Tracer;
+/// use rustracing::sampler::AllSampler;
+/// use rustracing::span::SpanLimits;
+///
+/// let limits = SpanLimits {
+///     max_logs: 1,
+///     ..SpanLimits::recommended()
+/// };
+/// let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+/// let tracer = Tracer::builder(AllSampler, span_tx).span_limits(limits).build();
+/// {
+///     let mut span = tracer.span("foo").start_with_state(());
+///     for _ in 0..3 {
+///         span.log(|log| {
+///             log.std().message("bar");
+///         });
+///     }
+/// }
+/// let span = span_rx.try_recv().unwrap();
+/// assert_eq!(span.logs().len(), 1);
+/// assert_eq!(span.dropped_logs_count(), 2);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanLimits {
+    /// The maximum number of tags in a span.
+    pub max_tags: usize,
+
+    /// The maximum number of logs in a span.
+    pub max_logs: usize,
+
+    /// The maximum number of fields in a log.
+    pub max_log_fields: usize,
+
+    /// The maximum number of baggage items in a span.
+    pub max_baggage_items: usize,
+
+    /// The maximum byte length of the string and bytes values of tags and log fields.
+    ///
+    /// Longer values are truncated (strings are truncated at a character boundary).
+    /// Each element of a string array is truncated individually.
+    pub max_value_len: usize,
+}
+impl SpanLimits {
+    /// The recommended value of `max_tags`.
+    pub const RECOMMENDED_MAX_TAGS: usize = 128;
+
+    /// The recommended value of `max_logs`.
+    pub const RECOMMENDED_MAX_LOGS: usize = 128;
+
+    /// The recommended value of `max_log_fields`.
+    pub const RECOMMENDED_MAX_LOG_FIELDS: usize = 128;
+
+    /// The recommended value of `max_baggage_items`.
+    pub const RECOMMENDED_MAX_BAGGAGE_ITEMS: usize = 128;
+
+    /// Makes a `SpanLimits` instance which has no limits.
+    ///
+    /// This is the same as `SpanLimits::default()`.
+    pub fn unlimited() -> Self {
+        SpanLimits {
+            max_tags: usize::MAX,
+            max_logs: usize::MAX,
+            max_log_fields: usize::MAX,
+            max_baggage_items: usize::MAX,
+            max_value_len: usize::MAX,
+        }
+    }
+
+    /// Makes a `SpanLimits` instance which has the recommended limits.
+    ///
+    /// The length of values is unlimited.
+    pub fn recommended() -> Self {
+        SpanLimits {
+            max_tags: Self::RECOMMENDED_MAX_TAGS,
+            max_logs: Self::RECOMMENDED_MAX_LOGS,
+            max_log_fields: Self::RECOMMENDED_MAX_LOG_FIELDS,
+            max_baggage_items: Self::RECOMMENDED_MAX_BAGGAGE_ITEMS,
+            max_value_len: usize::MAX,
+        }
+    }
+}
+impl Default for SpanLimits {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct DroppedCounts {
+    tags: usize,
+    logs: usize,
+    log_fields: usize,
+    baggage_items: usize,
+    truncated_values: usize,
+}
+
 #[derive(Debug)]
 struct SpanInner<T> {
     operation_name: Cow<'static, str>,
@@ -242,7 +593,72 @@ struct SpanInner<T> {
     tags: Vec<Tag>,
     logs: Vec<Log>,
     context: SpanContext<T>,
-    span_tx: SpanSender<T>,
+    sink: Arc<SpanSink<T>>,
+    kind: Option<SpanKind>,
+    status: SpanStatus,
+    dropped: DroppedCounts,
+    _panic_guard: Option<crate::panic::SpanGuard>,
+}
+impl<T> SpanInner<T> {
+    fn push_tag(&mut self, mut tag: Tag) {
+        let limits = self.sink.span_limits();
+        if let Some(i) = self.tags.iter().position(|x| x.name() == tag.name()) {
+            self.tags.remove(i);
+        } else if self.tags.len() >= limits.max_tags && tag.name() != "error" {
+            self.dropped.tags += 1;
+            return;
+        }
+        if tag.value_mut().truncate(limits.max_value_len) {
+            self.dropped.truncated_values += 1;
+        }
+        if tag.name() == "span.kind" {
+            self.kind = match tag.value() {
+                TagValue::String(kind) => kind.parse().ok(),
+                _ => None,
+            };
+        }
+        if tag.name() == "error" {
+            match tag.value() {
+                TagValue::Boolean(true) if !self.status.is_error() => {
+                    self.status = SpanStatus::error("");
+                }
+                TagValue::Boolean(false) if self.status.is_error() => {
+                    self.status = SpanStatus::Unset;
+                }
+                _ => {}
+            }
+        }
+        self.tags.push(tag);
+    }
+
+    fn set_status(&mut self, status: SpanStatus) {
+        if status.is_error() {
+            self.push_tag(StdTag::error());
+        } else {
+            self.tags.retain(|x| x.name() != "error");
+        }
+        self.status = status;
+    }
+
+    fn push_log(&mut self, mut builder: LogBuilder) {
+        let limits = self.sink.span_limits();
+        let dropped_fields = builder.truncate_fields(limits.max_log_fields);
+        let mut log = match builder.finish() {
+            Some(log) => log,
+            None => return,
+        };
+        if self.logs.len() >= limits.max_logs {
+            self.dropped.logs += 1;
+            return;
+        }
+        self.dropped.log_fields += dropped_fields;
+        for field in log.fields_mut() {
+            if field.truncate_value(limits.max_value_len) {
+                self.dropped.truncated_values += 1;
+            }
+        }
+        self.logs.push(log);
+    }
 }
 
 /// Finished span.
@@ -255,6 +671,9 @@ pub struct FinishedSpan<T> {
     tags: Vec<Tag>,
     logs: Vec<Log>,
     context: SpanContext<T>,
+    kind: Option<SpanKind>,
+    status: SpanStatus,
+    dropped: DroppedCounts,
 }
 impl<T> FinishedSpan<T> {
     /// Returns the operation name of this span.
@@ -291,6 +710,44 @@ impl<T> FinishedSpan<T> {
     pub fn context(&self) -> &SpanContext<T> {
         &self.context
     }
+
+    /// Returns the kind of this span.
+    ///
+    /// It is derived from the `"span.kind"` tag,
+    /// so it is `None` if the tag is absent or has an unknown value.
+    pub fn kind(&self) -> Option<SpanKind> {
+        self.kind
+    }
+
+    /// Returns the status of this span.
+    pub fn status(&self) -> &SpanStatus {
+        &self.status
+    }
+
+    /// Returns the number of the tags dropped due to `SpanLimits::max_tags`.
+    pub fn dropped_tags_count(&self) -> usize {
+        self.dropped.tags
+    }
+
+    /// Returns the number of the logs dropped due to `SpanLimits::max_logs`.
+    pub fn dropped_logs_count(&self) -> usize {
+        self.dropped.logs
+    }
+
+    /// Returns the number of the log fields dropped due to `SpanLimits::max_log_fields`.
+    pub fn dropped_log_fields_count(&self) -> usize {
+        self.dropped.log_fields
+    }
+
+    /// Returns the number of the baggage items dropped due to `SpanLimits::max_baggage_items`.
+    pub fn dropped_baggage_items_count(&self) -> usize {
+        self.dropped.baggage_items
+    }
+
+    /// Returns the number of the values truncated due to `SpanLimits::max_value_len`.
+    pub fn truncated_values_count(&self) -> usize {
+        self.dropped.truncated_values
+    }
 }
 
 /// Span context.
@@ -303,6 +760,7 @@ impl<T> FinishedSpan<T> {
 pub struct SpanContext<T> {
     state: T,
     baggage_items: Vec<BaggageItem>,
+    sampling_priority: Option<u32>,
 }
 impl<T> SpanContext<T> {
     /// Makes a new `SpanContext` instance.
@@ -313,9 +771,24 @@ impl<T> SpanContext<T> {
         SpanContext {
             state,
             baggage_items,
+            sampling_priority: None,
         }
     }
 
+    /// Sets the sampling priority hint of this context.
+    ///
+    /// The spans which refer to this context will have the `StdTag::sampling_priority(priority)` tag
+    /// unless the tag is set explicitly.
+    /// It is mainly used by propagators to pass the debug flag of an upstream context to the `Tracer`.
+    pub fn set_sampling_priority(&mut self, priority: u32) {
+        self.sampling_priority = Some(priority);
+    }
+
+    /// Returns the sampling priority hint of this context.
+    pub fn sampling_priority(&self) -> Option<u32> {
+        self.sampling_priority
+    }
+
     /// Returns the implementation-dependent state of this context.
     pub fn state(&self) -> &T {
         &self.state
@@ -326,6 +799,20 @@ impl<T> SpanContext<T> {
         &self.baggage_items
     }
 
+    /// Merges `items` into the baggage items of this context.
+    ///
+    /// If there are items that have the same name, they are replaced by the new ones.
+    pub fn merge_baggage_items<I>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = BaggageItem>,
+    {
+        for item in items {
+            self.baggage_items.retain(|x| x.name != item.name);
+            self.baggage_items.push(item);
+        }
+        self.baggage_items.sort_by(|a, b| a.name().cmp(b.name()));
+    }
+
     /// Injects this context to the **Text Map** `carrier`.
     pub fn inject_to_text_map<C>(&self, carrier: &mut C) -> Result<()>
     where
@@ -404,6 +891,7 @@ impl<T> MaybeAsRef<SpanContext<T>> for SpanContext<T> {
 pub struct BaggageItem {
     name: String,
     value: String,
+    properties: Vec<BaggageProperty>,
 }
 impl BaggageItem {
     /// Makes a new `BaggageItem` instance.
@@ -411,9 +899,21 @@ impl BaggageItem {
         BaggageItem {
             name: name.to_owned(),
             value: value.to_owned(),
+            properties: Vec::new(),
         }
     }
 
+    /// Sets the properties (i.e., metadata) of this item.
+    pub fn with_properties(mut self, properties: Vec<BaggageProperty>) -> Self {
+        self.properties = properties;
+        self
+    }
+
+    /// Returns the properties of this item.
+    pub fn properties(&self) -> &[BaggageProperty] {
+        &self.properties
+    }
+
     /// Returns the name of this item.
     pub fn name(&self) -> &str {
         &self.name
@@ -425,6 +925,35 @@ impl BaggageItem {
     }
 }
 
+/// Property of `BaggageItem`.
+///
+/// A property is either a key-value pair or a key without a value
+/// (cf. [W3C Baggage](https://www.w3.org/TR/baggage/)).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaggageProperty {
+    name: String,
+    value: Option<String>,
+}
+impl BaggageProperty {
+    /// Makes a new `BaggageProperty` instance.
+    pub fn new(name: &str, value: Option<&str>) -> Self {
+        BaggageProperty {
+            name: name.to_owned(),
+            value: value.map(|v| v.to_owned()),
+        }
+    }
+
+    /// Returns the name of this property.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value of this property.
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+}
+
 /// Span reference.
 #[derive(Debug, Clone)]
 #[allow(missing_docs)]
@@ -454,11 +983,18 @@ impl<T> SpanReference<T> {
 /// Candidate span for tracing.
 #[derive(Debug)]
 pub struct CandidateSpan<'a, T: 'a> {
+    operation_name: &'a str,
+    root_trace_id: &'a OnceCell<TraceId>,
     tags: &'a [Tag],
     references: &'a [SpanReference<T>],
     baggage_items: &'a [BaggageItem],
 }
 impl<'a, T: 'a> CandidateSpan<'a, T> {
+    /// Returns the operation name of this span.
+    pub fn operation_name(&self) -> &str {
+        self.operation_name
+    }
+
     /// Returns the tags of this span.
     pub fn tags(&self) -> &[Tag] {
         self.tags
@@ -473,6 +1009,40 @@ impl<'a, T: 'a> CandidateSpan<'a, T> {
     pub fn baggage_items(&self) -> &[BaggageItem] {
         self.baggage_items
     }
+
+    /// Returns the span context state of the parent of this span.
+    ///
+    /// The parent is the span of the first `ChildOf` reference
+    /// (or the first `FollowsFrom` reference if there are no `ChildOf` references).
+    pub fn parent(&self) -> Option<&T> {
+        self.references
+            .iter()
+            .find(|r| r.is_child_of())
+            .or_else(|| self.references.first())
+            .map(|r| r.span())
+    }
+
+    /// Returns the trace identifier which is used if this span starts a new trace.
+    ///
+    /// The identifier is generated on the first call,
+    /// and the same value is returned for the span afterwards.
+    /// So samplers can make decisions based on the identifier of a new trace
+    /// before the span context state is created.
+    pub fn root_trace_id(&self) -> TraceId {
+        *self.root_trace_id.get_or_init(TraceId::new)
+    }
+
+    /// Returns the trace identifier of this span.
+    ///
+    /// If this span has a parent, it is the trace identifier of the parent.
+    /// Otherwise, it is `root_trace_id()`.
+    pub fn trace_id(&self) -> TraceId
+    where
+        T: TraceIdState,
+    {
+        self.parent()
+            .map_or_else(|| self.root_trace_id(), |p| p.trace_id())
+    }
 }
 
 /// Options for starting a span.
@@ -483,7 +1053,8 @@ pub struct StartSpanOptions<'a, S: 'a, T: 'a> {
     tags: Vec<Tag>,
     references: Vec<SpanReference<T>>,
     baggage_items: Vec<BaggageItem>,
-    span_tx: &'a SpanSender<T>,
+    root_trace_id: OnceCell<TraceId>,
+    sink: &'a Arc<SpanSink<T>>,
     sampler: &'a S,
 }
 impl<'a, S: 'a, T: 'a> StartSpanOptions<'a, S, T>
@@ -502,6 +1073,13 @@ where
         self
     }
 
+    /// Sets the kind of this span.
+    ///
+    /// This is equivalent to `self.tag(StdTag::span_kind(kind))`.
+    pub fn kind(self, kind: SpanKind) -> Self {
+        self.tag(StdTag::span_kind(kind))
+    }
+
     /// Adds the `ChildOf` reference to this span.
     pub fn child_of<C>(mut self, context: &C) -> Self
     where
@@ -513,6 +1091,9 @@ where
             self.references.push(reference);
             self.baggage_items
                 .extend(context.baggage_items().iter().cloned());
+            if let Some(priority) = context.sampling_priority() {
+                self.tags.insert(0, StdTag::sampling_priority(priority));
+            }
         }
         self
     }
@@ -528,6 +1109,9 @@ where
             self.references.push(reference);
             self.baggage_items
                 .extend(context.baggage_items().iter().cloned());
+            if let Some(priority) = context.sampling_priority() {
+                self.tags.insert(0, StdTag::sampling_priority(priority));
+            }
         }
         self
     }
@@ -549,7 +1133,7 @@ where
             self.tags,
             state,
             self.baggage_items,
-            self.span_tx.clone(),
+            Arc::clone(self.sink),
         )
     }
 
@@ -566,11 +1150,11 @@ where
             self.tags,
             state,
             self.baggage_items,
-            self.span_tx.clone(),
+            Arc::clone(self.sink),
         )
     }
 
-    pub(crate) fn new<N>(operation_name: N, span_tx: &'a SpanSender<T>, sampler: &'a S) -> Self
+    pub(crate) fn new<N>(operation_name: N, sink: &'a Arc<SpanSink<T>>, sampler: &'a S) -> Self
     where
         N: Into<Cow<'static, str>>,
     {
@@ -580,7 +1164,8 @@ where
             tags: Vec::new(),
             references: Vec::new(),
             baggage_items: Vec::new(),
-            span_tx,
+            root_trace_id: OnceCell::new(),
+            sink,
             sampler,
         }
     }
@@ -595,8 +1180,10 @@ where
         self.baggage_items.dedup_by(|a, b| a.name() == b.name());
     }
 
-    fn span(&self) -> CandidateSpan<T> {
+    fn span(&self) -> CandidateSpan<'_, T> {
         CandidateSpan {
+            operation_name: &self.operation_name,
+            root_trace_id: &self.root_trace_id,
             references: &self.references,
             tags: &self.tags,
             baggage_items: &self.baggage_items,
@@ -604,22 +1191,26 @@ where
     }
 
     fn is_sampled(&self) -> bool {
-        if let Some(&TagValue::Integer(n)) = self
+        let priority = self
             .tags
             .iter()
             .find(|t| t.name() == "sampling.priority")
-            .map(|t| t.value())
-        {
-            n > 0
-        } else {
-            self.sampler.is_sampled(&self.span())
+            .map(|t| t.value());
+        match priority {
+            Some(&TagValue::Integer(n)) => n > 0,
+            Some(&TagValue::U64(n)) => n > 0,
+            _ => {
+                // Only root spans are sampled down so that whole traces are dropped.
+                self.sampler.is_sampled(&self.span())
+                    && (!self.references.is_empty() || !self.sink.should_sample_down())
+            }
         }
     }
 }
 
 /// Immutable handle of `Span`.
 #[derive(Debug, Clone)]
-pub struct SpanHandle<T>(Option<(SpanContext<T>, SpanSender<T>)>);
+pub struct SpanHandle<T>(Option<(SpanContext<T>, Arc<SpanSink<T>>)>);
 impl<T> SpanHandle<T> {
     /// Returns `true` if this span is sampled (i.e., being traced).
     pub fn is_sampled(&self) -> bool {
@@ -647,9 +1238,9 @@ impl<T> SpanHandle<T> {
         T: Clone,
         F: FnOnce(StartSpanOptions<AllSampler, T>) -> Span<T>,
     {
-        if let Some((context, span_tx)) = self.0.as_ref() {
+        if let Some((context, sink)) = self.0.as_ref() {
             let options =
-                StartSpanOptions::new(operation_name, span_tx, &AllSampler).child_of(context);
+                StartSpanOptions::new(operation_name, sink, &AllSampler).child_of(context);
             f(options)
         } else {
             Span::inactive()
@@ -663,12 +1254,17 @@ impl<T> SpanHandle<T> {
         T: Clone,
         F: FnOnce(StartSpanOptions<AllSampler, T>) -> Span<T>,
     {
-        if let Some((context, span_tx)) = self.0.as_ref() {
+        if let Some((context, sink)) = self.0.as_ref() {
             let options =
-                StartSpanOptions::new(operation_name, span_tx, &AllSampler).follows_from(context);
+                StartSpanOptions::new(operation_name, sink, &AllSampler).follows_from(context);
             f(options)
         } else {
             Span::inactive()
         }
     }
 }
+impl<T> MaybeAsRef<SpanContext<T>> for SpanHandle<T> {
+    fn maybe_as_ref(&self) -> Option<&SpanContext<T>> {
+        self.context()
+    }
+}
@@ -0,0 +1,515 @@
+//! Built-in span context state.
+//!
+//! `SpanContextState` is a ready-made implementation-dependent state (i.e., `T` of `SpanContext<T>`)
+//! that holds [W3C Trace Context][trace-context] compatible identifiers.
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::sampler::AllSampler;
+//! use rustracing::state::SpanContextState;
+//! use rustracing::Tracer;
+//!
+//! let (tracer, span_rx) = Tracer::<_, SpanContextState>::new(AllSampler);
+//! {
+//!     let parent = tracer.span("parent").start();
+//!     let _child = tracer.span("child").child_of(&parent).start();
+//! }
+//!
+//! let child = span_rx.try_recv().unwrap();
+//! let parent = span_rx.try_recv().unwrap();
+//! assert_eq!(child.context().state().trace_id(), parent.context().state().trace_id());
+//! assert_eq!(
+//!     child.context().state().parent_span_id(),
+//!     Some(parent.context().state().span_id())
+//! );
+//! ```
+//!
+//! [trace-context]: https://www.w3.org/TR/trace-context/
+use crate::sampler::{ParentState, TraceIdState};
+use crate::span::CandidateSpan;
+use crate::{Error, ErrorKind, Result};
+use std::fmt;
+use std::str::FromStr;
+use trackable::error::ErrorKindExt;
+
+/// The flag which indicates that the span is sampled.
+pub const FLAG_SAMPLED: u8 = 0b01;
+
+/// The flag which indicates that the span is a debug one.
+pub const FLAG_DEBUG: u8 = 0b10;
+
+/// The maximum number of the entries in a `TraceState`.
+pub const MAX_TRACE_STATE_ENTRIES: usize = 32;
+
+/// 128-bit trace identifier.
+///
+/// The all-zero value is invalid and is never generated by `TraceId::new()`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TraceId {
+    /// The upper 64 bits of the identifier.
+    pub high: u64,
+
+    /// The lower 64 bits of the identifier.
+    pub low: u64,
+}
+impl TraceId {
+    /// Makes a new random `TraceId` instance.
+    pub fn new() -> Self {
+        TraceId {
+            high: rand::random(),
+            low: random_nonzero_u64(),
+        }
+    }
+
+    /// Returns `true` if this identifier is not all-zero.
+    pub fn is_valid(&self) -> bool {
+        self.high != 0 || self.low != 0
+    }
+
+    /// Returns the 128-bit integer representation of this identifier.
+    pub fn to_u128(&self) -> u128 {
+        (u128::from(self.high) << 64) | u128::from(self.low)
+    }
+}
+impl From<u128> for TraceId {
+    fn from(f: u128) -> Self {
+        TraceId {
+            high: (f >> 64) as u64,
+            low: f as u64,
+        }
+    }
+}
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}{:016x}", self.high, self.low)
+    }
+}
+impl FromStr for TraceId {
+    type Err = Error;
+
+    /// Parses a hexadecimal string which has at most 32 characters.
+    ///
+    /// Shorter strings (e.g., 64-bit trace identifiers) are zero-padded on the left.
+    fn from_str(s: &str) -> Result<Self> {
+        track_assert!(!s.is_empty(), ErrorKind::InvalidInput);
+        track_assert!(s.len() <= 32, ErrorKind::InvalidInput, "Too long: {:?}", s);
+        let n = track!(parse_hex_u128(s))?;
+        Ok(TraceId::from(n))
+    }
+}
+
+/// Vendor-specific trace identification data (i.e., the value of the W3C `tracestate` header).
+///
+/// Entries are kept in the order of the header value,
+/// and the most recently updated entry is placed at the beginning.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::state::TraceState;
+///
+/// let mut state: TraceState = "congo=t61rcWkgMzE,rojo=00f067aa0ba902b7".parse().unwrap();
+/// assert_eq!(state.get("rojo"), Some("00f067aa0ba902b7"));
+///
+/// state.insert("rojo", "b7ad6b7169203331").unwrap();
+/// assert_eq!(state.to_string(), "rojo=b7ad6b7169203331,congo=t61rcWkgMzE");
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct TraceState(Vec<(String, String)>);
+impl TraceState {
+    /// Makes a new empty `TraceState` instance.
+    pub fn new() -> Self {
+        TraceState(Vec::new())
+    }
+
+    /// Returns `true` if this has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the entries of this trace state.
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.0
+    }
+
+    /// Gets the value of the entry that has the key `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|e| e.0 == key).map(|e| e.1.as_str())
+    }
+
+    /// Inserts the entry to the beginning of this trace state.
+    ///
+    /// If there is an entry that has the same key, it will be removed.
+    /// If the number of the entries exceeds `MAX_TRACE_STATE_ENTRIES`,
+    /// the last entry will be discarded.
+    ///
+    /// # Errors
+    ///
+    /// If `key` or `value` violates the syntax of the W3C Trace Context specification,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn insert(&mut self, key: &str, value: &str) -> Result<()> {
+        track_assert!(is_valid_trace_state_key(key), ErrorKind::InvalidInput; key);
+        track_assert!(is_valid_trace_state_value(value), ErrorKind::InvalidInput; value);
+        self.0.retain(|e| e.0 != key);
+        self.0.insert(0, (key.to_owned(), value.to_owned()));
+        self.0.truncate(MAX_TRACE_STATE_ENTRIES);
+        Ok(())
+    }
+
+    /// Removes the entry that has the key `key`.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let i = self.0.iter().position(|e| e.0 == key)?;
+        Some(self.0.remove(i).1)
+    }
+}
+impl fmt::Display for TraceState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+impl FromStr for TraceState {
+    type Err = Error;
+
+    /// Parses a `tracestate` header value.
+    ///
+    /// If the value is malformed, it will return an error with the kind `ErrorKind::MalformedHeader`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut entries: Vec<(String, String)> = Vec::new();
+        for member in s.split(',') {
+            let member = member.trim_matches(|c| c == ' ' || c == '\t');
+            if member.is_empty() {
+                continue;
+            }
+            let mut kv = member.splitn(2, '=');
+            let key = kv.next().unwrap_or("");
+            let value = track_assert_some!(kv.next(), ErrorKind::MalformedHeader; member);
+            track_assert!(is_valid_trace_state_key(key), ErrorKind::MalformedHeader; key);
+            track_assert!(is_valid_trace_state_value(value), ErrorKind::MalformedHeader; value);
+            track_assert!(
+                entries.iter().all(|e| e.0 != key),
+                ErrorKind::MalformedHeader,
+                "Duplicate key: {:?}",
+                key
+            );
+            entries.push((key.to_owned(), value.to_owned()));
+        }
+        track_assert!(
+            entries.len() <= MAX_TRACE_STATE_ENTRIES,
+            ErrorKind::MalformedHeader,
+            "Too many entries: {}",
+            entries.len()
+        );
+        Ok(TraceState(entries))
+    }
+}
+
+/// Built-in span context state.
+///
+/// It consists of a 128-bit trace identifier, a 64-bit span identifier,
+/// the identifier of the parent span, sampled/debug flags and the vendor-specific `TraceState`.
+///
+/// When a span is started by `StartSpanOptions::start()`,
+/// the state is derived from the first `ChildOf` reference
+/// (or the first `FollowsFrom` reference if there are no `ChildOf` references) of the span.
+/// If the span has no references, `CandidateSpan::root_trace_id()` is used as the trace identifier.
+///
+/// The carrier traits (e.g., `carrier::InjectToHttpHeader`) are implemented for this state
+/// by using the built-in propagators in the `propagation` module.
+/// The states extracted by the propagators are marked as remote (see `is_remote()`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpanContextState {
+    trace_id: TraceId,
+    span_id: u64,
+    parent_span_id: u64,
+    flags: u8,
+    trace_state: TraceState,
+    remote: bool,
+    deferred: bool,
+}
+impl SpanContextState {
+    /// Makes a new `SpanContextState` instance.
+    ///
+    /// The parent span identifier and the flags are initialized with zero,
+    /// and the state is neither remote nor deferred.
+    pub fn new(trace_id: TraceId, span_id: u64) -> Self {
+        SpanContextState {
+            trace_id,
+            span_id,
+            parent_span_id: 0,
+            flags: 0,
+            trace_state: TraceState::new(),
+            remote: false,
+            deferred: false,
+        }
+    }
+
+    /// Makes a new sampled state which starts a new trace.
+    pub fn root() -> Self {
+        SpanContextState::new(TraceId::new(), random_nonzero_u64()).with_flags(FLAG_SAMPLED)
+    }
+
+    /// Sets the identifier of the parent span of this state.
+    ///
+    /// `0` means that there is no parent span.
+    pub fn with_parent_span_id(mut self, parent_span_id: u64) -> Self {
+        self.parent_span_id = parent_span_id;
+        self
+    }
+
+    /// Sets the flags of this state.
+    pub fn with_flags(mut self, flags: u8) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Sets the vendor-specific trace state of this state.
+    pub fn with_trace_state(mut self, trace_state: TraceState) -> Self {
+        self.trace_state = trace_state;
+        self
+    }
+
+    /// Sets whether this state has been propagated from a remote process.
+    pub fn with_remote(mut self, remote: bool) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    /// Sets whether the sampling decision of this state is deferred to the receiver.
+    ///
+    /// For example, the B3 propagator marks the extracted state as deferred
+    /// if the carrier does not contain the sampling state.
+    pub fn with_deferred(mut self, deferred: bool) -> Self {
+        self.deferred = deferred;
+        self
+    }
+
+    /// Returns the trace identifier of this state.
+    pub fn trace_id(&self) -> TraceId {
+        self.trace_id
+    }
+
+    /// Returns the span identifier of this state.
+    pub fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    /// Returns the identifier of the parent span of this state.
+    pub fn parent_span_id(&self) -> Option<u64> {
+        if self.parent_span_id == 0 {
+            None
+        } else {
+            Some(self.parent_span_id)
+        }
+    }
+
+    /// Returns the flags of this state.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns the vendor-specific trace state of this state.
+    pub fn trace_state(&self) -> &TraceState {
+        &self.trace_state
+    }
+
+    /// Returns a mutable reference to the vendor-specific trace state of this state.
+    pub fn trace_state_mut(&mut self) -> &mut TraceState {
+        &mut self.trace_state
+    }
+
+    /// Returns `true` if the `FLAG_SAMPLED` flag is set.
+    pub fn is_sampled(&self) -> bool {
+        (self.flags & FLAG_SAMPLED) != 0
+    }
+
+    /// Returns `true` if the `FLAG_DEBUG` flag is set.
+    pub fn is_debug(&self) -> bool {
+        (self.flags & FLAG_DEBUG) != 0
+    }
+
+    /// Returns `true` if this state has been propagated from a remote process
+    /// (e.g., extracted from HTTP headers).
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    /// Returns `true` if the sampling decision of this state is deferred to the receiver.
+    ///
+    /// In that case, the sampled flag is meaningless
+    /// and the local sampler should decide whether the children are sampled.
+    pub fn is_deferred(&self) -> bool {
+        self.deferred
+    }
+
+    /// Returns `true` if both of the trace and span identifiers are non-zero.
+    pub fn is_valid(&self) -> bool {
+        self.trace_id.is_valid() && self.span_id != 0
+    }
+}
+impl ParentState for SpanContextState {
+    fn is_sampled(&self) -> bool {
+        self.is_sampled()
+    }
+
+    fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    fn is_deferred(&self) -> bool {
+        self.deferred
+    }
+}
+impl TraceIdState for SpanContextState {
+    fn trace_id(&self) -> TraceId {
+        self.trace_id
+    }
+}
+impl<'a> From<CandidateSpan<'a, Self>> for SpanContextState {
+    fn from(f: CandidateSpan<'a, Self>) -> Self {
+        if let Some(parent) = f.parent() {
+            SpanContextState::new(parent.trace_id, random_nonzero_u64())
+                .with_parent_span_id(parent.span_id)
+                .with_flags(parent.flags | FLAG_SAMPLED)
+                .with_trace_state(parent.trace_state.clone())
+        } else {
+            SpanContextState::new(f.root_trace_id(), random_nonzero_u64()).with_flags(FLAG_SAMPLED)
+        }
+    }
+}
+
+fn random_nonzero_u64() -> u64 {
+    loop {
+        let n = rand::random();
+        if n != 0 {
+            return n;
+        }
+    }
+}
+
+fn is_valid_trace_state_key(s: &str) -> bool {
+    fn is_key_char(b: u8) -> bool {
+        matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'*' | b'/')
+    }
+
+    let mut parts = s.splitn(2, '@');
+    let tenant = parts.next().unwrap_or("").as_bytes();
+    if let Some(system) = parts.next().map(|x| x.as_bytes()) {
+        !tenant.is_empty()
+            && tenant.len() <= 241
+            && (tenant[0].is_ascii_lowercase() || tenant[0].is_ascii_digit())
+            && tenant.iter().all(|&b| is_key_char(b))
+            && !system.is_empty()
+            && system.len() <= 14
+            && system[0].is_ascii_lowercase()
+            && system.iter().all(|&b| is_key_char(b))
+    } else {
+        !tenant.is_empty()
+            && tenant.len() <= 256
+            && tenant[0].is_ascii_lowercase()
+            && tenant.iter().all(|&b| is_key_char(b))
+    }
+}
+
+fn is_valid_trace_state_value(s: &str) -> bool {
+    !s.is_empty()
+        && s.len() <= 256
+        && !s.ends_with(' ')
+        && s.bytes()
+            .all(|b| (0x20..=0x7e).contains(&b) && b != b',' && b != b'=')
+}
+
+fn parse_hex_u128(s: &str) -> Result<u128> {
+    track_assert!(
+        s.bytes().all(|b| b.is_ascii_hexdigit()),
+        ErrorKind::InvalidInput,
+        "Not a hexadecimal string: {:?}",
+        s
+    );
+    u128::from_str_radix(s, 16).map_err(|e| track!(Error::from(ErrorKind::InvalidInput.cause(e))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::Tracer;
+
+    #[test]
+    fn trace_id_works() {
+        let id = TraceId {
+            high: 0x0123_4567_89ab_cdef,
+            low: 0x1,
+        };
+        assert_eq!(id.to_string(), "0123456789abcdef0000000000000001");
+        assert_eq!(id.to_string().parse::<TraceId>().ok(), Some(id));
+        assert_eq!(
+            "1".parse::<TraceId>().ok(),
+            Some(TraceId { high: 0, low: 1 })
+        );
+        assert!("".parse::<TraceId>().is_err());
+        assert!("+1".parse::<TraceId>().is_err());
+        assert!("0123456789abcdef0123456789abcdef0"
+            .parse::<TraceId>()
+            .is_err());
+        assert!(TraceId::new().is_valid());
+        assert!(!TraceId::default().is_valid());
+    }
+
+    #[test]
+    fn trace_state_works() {
+        let state: TraceState = " a=1 ,, b@c=x y\t".parse().unwrap();
+        assert_eq!(state.entries().len(), 2);
+        assert_eq!(state.get("b@c"), Some("x y"));
+        assert_eq!(state.to_string(), "a=1,b@c=x y");
+
+        assert!("A=1".parse::<TraceState>().is_err());
+        assert!("a".parse::<TraceState>().is_err());
+        assert!("a=1,a=2".parse::<TraceState>().is_err());
+        assert!("a=1=2".parse::<TraceState>().is_err());
+        assert!("a@=1".parse::<TraceState>().is_err());
+
+        let too_many = (0..33).map(|i| format!("k{}=v", i)).collect::<Vec<_>>();
+        assert!(too_many.join(",").parse::<TraceState>().is_err());
+
+        let mut state = TraceState::new();
+        for i in 0..33 {
+            state.insert(&format!("k{}", i), "v").unwrap();
+        }
+        assert_eq!(state.entries().len(), MAX_TRACE_STATE_ENTRIES);
+        assert_eq!(state.entries()[0].0, "k32");
+        assert!(state.insert("k", "").is_err());
+    }
+
+    #[test]
+    fn child_state_works() {
+        let (tracer, span_rx) = Tracer::<_, SpanContextState>::new(AllSampler);
+        {
+            let parent = tracer.span("parent").start();
+            let _follower = tracer.span("follower").follows_from(&parent).start();
+            let _child = parent.child("child", |options| options.start());
+        }
+
+        let child = span_rx.try_recv().unwrap();
+        let follower = span_rx.try_recv().unwrap();
+        let parent = span_rx.try_recv().unwrap();
+
+        let parent = parent.context().state();
+        assert!(parent.is_valid());
+        assert!(parent.is_sampled());
+        assert_eq!(parent.parent_span_id(), None);
+        for span in [&child, &follower] {
+            let state = span.context().state();
+            assert_eq!(state.trace_id(), parent.trace_id());
+            assert_eq!(state.parent_span_id(), Some(parent.span_id()));
+            assert_ne!(state.span_id(), parent.span_id());
+            assert!(state.is_sampled());
+        }
+    }
+}
@@ -38,9 +38,26 @@ impl Tag {
     pub fn value(&self) -> &TagValue {
         &self.value
     }
+
+    pub(crate) fn value_mut(&mut self) -> &mut TagValue {
+        &mut self.value
+    }
 }
 
 /// Span tag value.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::tag::{TagArray, TagValue};
+///
+/// assert_eq!(TagValue::from(u64::MAX), TagValue::U64(u64::MAX));
+/// assert_eq!(
+///     TagValue::from(vec!["a", "b"]),
+///     TagValue::Array(TagArray::String(vec!["a".into(), "b".into()]))
+/// );
+/// assert_eq!(TagValue::from(vec![0u8, 1]), TagValue::Bytes(vec![0, 1].into()));
+/// ```
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 #[allow(missing_docs)]
 pub enum TagValue {
@@ -48,6 +65,73 @@ pub enum TagValue {
     Boolean(bool),
     Integer(i64),
     Float(f64),
+    U64(u64),
+    Array(TagArray),
+    Bytes(Cow<'static, [u8]>),
+}
+impl TagValue {
+    /// Truncates the value to at most `max_len` bytes if it is a string or bytes.
+    ///
+    /// In the case of a string array, each element is truncated.
+    /// Returns `true` if the value has been truncated.
+    pub(crate) fn truncate(&mut self, max_len: usize) -> bool {
+        match self {
+            TagValue::String(s) => truncate_str(s, max_len),
+            TagValue::Array(TagArray::String(a)) => {
+                let mut truncated = false;
+                for s in a {
+                    truncated |= truncate_str(s, max_len);
+                }
+                truncated
+            }
+            TagValue::Bytes(b) if b.len() > max_len => {
+                b.to_mut().truncate(max_len);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the string value if this is `TagValue::String`.
+    pub fn as_str(&self) -> Option<&str> {
+        if let TagValue::String(s) = self {
+            Some(s.as_ref())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the textual representation of this value.
+    ///
+    /// Arrays are represented as comma-separated elements and bytes as lowercase hexadecimal digits.
+    pub(crate) fn to_text(&self) -> Cow<'_, str> {
+        fn join<T: ToString>(a: &[T]) -> String {
+            a.iter().map(T::to_string).collect::<Vec<_>>().join(",")
+        }
+        match self {
+            TagValue::String(s) => Cow::Borrowed(s.as_ref()),
+            TagValue::Boolean(v) => Cow::Owned(v.to_string()),
+            TagValue::Integer(v) => Cow::Owned(v.to_string()),
+            TagValue::Float(v) => Cow::Owned(v.to_string()),
+            TagValue::U64(v) => Cow::Owned(v.to_string()),
+            TagValue::Array(TagArray::String(a)) => Cow::Owned(a.join(",")),
+            TagValue::Array(TagArray::Boolean(a)) => Cow::Owned(join(a)),
+            TagValue::Array(TagArray::Integer(a)) => Cow::Owned(join(a)),
+            TagValue::Array(TagArray::Float(a)) => Cow::Owned(join(a)),
+            TagValue::Array(TagArray::U64(a)) => Cow::Owned(join(a)),
+            TagValue::Bytes(b) => Cow::Owned(b.iter().map(|b| format!("{:02x}", b)).collect()),
+        }
+    }
+}
+impl PartialEq<str> for TagValue {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == Some(other)
+    }
+}
+impl<'a> PartialEq<&'a str> for TagValue {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == Some(*other)
+    }
 }
 impl From<&'static str> for TagValue {
     fn from(f: &'static str) -> Self {
@@ -79,6 +163,109 @@ impl From<f64> for TagValue {
         TagValue::Float(f)
     }
 }
+impl From<i32> for TagValue {
+    fn from(f: i32) -> Self {
+        TagValue::Integer(i64::from(f))
+    }
+}
+impl From<u32> for TagValue {
+    fn from(f: u32) -> Self {
+        TagValue::Integer(i64::from(f))
+    }
+}
+impl From<u64> for TagValue {
+    fn from(f: u64) -> Self {
+        TagValue::U64(f)
+    }
+}
+impl From<TagArray> for TagValue {
+    fn from(f: TagArray) -> Self {
+        TagValue::Array(f)
+    }
+}
+impl From<Vec<&'static str>> for TagValue {
+    fn from(f: Vec<&'static str>) -> Self {
+        TagValue::Array(TagArray::String(f.into_iter().map(Cow::Borrowed).collect()))
+    }
+}
+impl From<Vec<String>> for TagValue {
+    fn from(f: Vec<String>) -> Self {
+        TagValue::Array(TagArray::String(f.into_iter().map(Cow::Owned).collect()))
+    }
+}
+impl From<Vec<bool>> for TagValue {
+    fn from(f: Vec<bool>) -> Self {
+        TagValue::Array(TagArray::Boolean(f))
+    }
+}
+impl From<Vec<i64>> for TagValue {
+    fn from(f: Vec<i64>) -> Self {
+        TagValue::Array(TagArray::Integer(f))
+    }
+}
+impl From<Vec<f64>> for TagValue {
+    fn from(f: Vec<f64>) -> Self {
+        TagValue::Array(TagArray::Float(f))
+    }
+}
+impl From<Vec<u64>> for TagValue {
+    fn from(f: Vec<u64>) -> Self {
+        TagValue::Array(TagArray::U64(f))
+    }
+}
+impl From<Vec<u8>> for TagValue {
+    fn from(f: Vec<u8>) -> Self {
+        TagValue::Bytes(Cow::Owned(f))
+    }
+}
+impl From<&'static [u8]> for TagValue {
+    fn from(f: &'static [u8]) -> Self {
+        TagValue::Bytes(Cow::Borrowed(f))
+    }
+}
+
+/// Homogeneous array of tag values.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[allow(missing_docs)]
+pub enum TagArray {
+    String(Vec<Cow<'static, str>>),
+    Boolean(Vec<bool>),
+    Integer(Vec<i64>),
+    Float(Vec<f64>),
+    U64(Vec<u64>),
+}
+impl TagArray {
+    /// Returns the number of the elements in this array.
+    pub fn len(&self) -> usize {
+        match self {
+            TagArray::String(a) => a.len(),
+            TagArray::Boolean(a) => a.len(),
+            TagArray::Integer(a) => a.len(),
+            TagArray::Float(a) => a.len(),
+            TagArray::U64(a) => a.len(),
+        }
+    }
+
+    /// Returns `true` if this array has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Truncates `s` to at most `max_len` bytes at a character boundary.
+///
+/// Returns `true` if `s` has been truncated.
+pub(crate) fn truncate_str(s: &mut Cow<'static, str>, max_len: usize) -> bool {
+    if s.len() <= max_len {
+        return false;
+    }
+    let mut len = max_len;
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    s.to_mut().truncate(len);
+    true
+}
 
 /// [Standard span tags][tags].
 /// [tags]: https://github.com/opentracing/specification/blob/master/semantic_conventions.md#span-tags-table
@@ -279,6 +466,8 @@ impl StdTag {
     ///
     /// Either `"client"` or `"server"` for the appropriate roles in an RPC,
     /// and `"producer"` or `"consumer"` for the appropriate roles in a messaging scenario.
+    ///
+    /// `SpanKind` can be used as a typed `value`.
     pub fn span_kind<V>(value: V) -> Tag
     where
         V: Into<Cow<'static, str>>,
@@ -0,0 +1,207 @@
+//! Helpers for propagating the active span to other threads.
+//!
+//! The active span (see the `scope` module) is thread-local,
+//! so it is lost when a job is handed to another thread.
+//! `CapturedSpan` captures it and re-activates it (or starts a new span referring to it)
+//! in the thread which runs the job.
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::sampler::AllSampler;
+//! use rustracing::scope;
+//! use rustracing::span::Span;
+//! use rustracing::state::SpanContextState;
+//! use rustracing::tag::Tag;
+//! use rustracing::thread;
+//! use rustracing::Tracer;
+//!
+//! let (tracer, span_rx) = Tracer::new(AllSampler);
+//! {
+//!     let parent: Span<SpanContextState> = tracer.span("parent").start();
+//!     let _scope = scope::activate(parent.handle());
+//!
+//!     let handle = thread::spawn_child::<SpanContextState, _, _, _>("child", |span| {
+//!         span.set_tag(|| Tag::new("worker", true));
+//!     });
+//!     handle.join().unwrap();
+//! }
+//!
+//! let child = span_rx.try_recv().unwrap();
+//! assert_eq!(child.operation_name(), "child");
+//! assert!(child.references()[0].is_child_of());
+//! ```
+use crate::scope;
+use crate::span::{CandidateSpan, Span, SpanHandle};
+use std::borrow::Cow;
+use std::thread::{self, JoinHandle};
+
+/// Spawns a new thread which runs `f` within a `ChildOf` span of the currently active span.
+///
+/// The new span is active in the spawned thread while `f` is running,
+/// and it is finished when `f` returns.
+/// If there is no active span of the type `T`, the span is not sampled.
+pub fn spawn_child<T, N, F, R>(operation_name: N, f: F) -> JoinHandle<R>
+where
+    T: for<'a> From<CandidateSpan<'a, T>> + Clone + Send + 'static,
+    N: Into<Cow<'static, str>>,
+    F: FnOnce(&mut Span<T>) -> R + Send + 'static,
+    R: Send + 'static,
+{
+    thread::spawn(CapturedSpan::current().child(operation_name.into(), f))
+}
+
+/// Spawns a new thread which runs `f` within a `FollowsFrom` span of the currently active span.
+///
+/// See `spawn_child()` for details.
+pub fn spawn_follower<T, N, F, R>(operation_name: N, f: F) -> JoinHandle<R>
+where
+    T: for<'a> From<CandidateSpan<'a, T>> + Clone + Send + 'static,
+    N: Into<Cow<'static, str>>,
+    F: FnOnce(&mut Span<T>) -> R + Send + 'static,
+    R: Send + 'static,
+{
+    thread::spawn(CapturedSpan::current().follower(operation_name.into(), f))
+}
+
+/// Span captured in a thread for use in another thread.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::sampler::AllSampler;
+/// use rustracing::scope;
+/// use rustracing::thread::CapturedSpan;
+/// use rustracing::Tracer;
+///
+/// let (tracer, _span_rx) = Tracer::new(AllSampler);
+/// let span = tracer.span("foo").start_with_state(1);
+/// let _scope = scope::activate(span.handle());
+///
+/// // Submits a job to a thread pool (a plain thread here).
+/// let job = CapturedSpan::<i32>::current().wrap(|| {
+///     scope::active::<i32>().and_then(|h| h.context().map(|c| *c.state()))
+/// });
+/// assert_eq!(std::thread::spawn(job).join().unwrap(), Some(1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CapturedSpan<T> {
+    handle: Option<SpanHandle<T>>,
+}
+impl<T: Clone + 'static> CapturedSpan<T> {
+    /// Captures the span active for the current thread.
+    pub fn current() -> Self {
+        CapturedSpan {
+            handle: scope::active(),
+        }
+    }
+
+    /// Makes a new `CapturedSpan` instance which holds `handle`.
+    pub fn new(handle: SpanHandle<T>) -> Self {
+        CapturedSpan {
+            handle: Some(handle),
+        }
+    }
+
+    /// Returns the handle of the captured span.
+    pub fn handle(&self) -> Option<&SpanHandle<T>> {
+        self.handle.as_ref()
+    }
+
+    /// Wraps `f` so that the captured span is active while it is running.
+    pub fn wrap<F, R>(self, f: F) -> impl FnOnce() -> R
+    where
+        F: FnOnce() -> R,
+    {
+        move || {
+            let _scope = self.handle.map(scope::activate);
+            f()
+        }
+    }
+
+    /// Wraps `f` so that it runs within a new `ChildOf` span of the captured span.
+    ///
+    /// The new span is active while `f` is running, and it is finished when `f` returns.
+    pub fn child<N, F, R>(self, operation_name: N, f: F) -> impl FnOnce() -> R
+    where
+        T: for<'a> From<CandidateSpan<'a, T>>,
+        N: Into<Cow<'static, str>>,
+        F: FnOnce(&mut Span<T>) -> R,
+    {
+        let operation_name = operation_name.into();
+        move || {
+            let span = self.handle.map_or_else(Span::inactive, |handle| {
+                handle.child(operation_name, |options| options.start())
+            });
+            run_in_span(span, f)
+        }
+    }
+
+    /// Wraps `f` so that it runs within a new `FollowsFrom` span of the captured span.
+    ///
+    /// The new span is active while `f` is running, and it is finished when `f` returns.
+    pub fn follower<N, F, R>(self, operation_name: N, f: F) -> impl FnOnce() -> R
+    where
+        T: for<'a> From<CandidateSpan<'a, T>>,
+        N: Into<Cow<'static, str>>,
+        F: FnOnce(&mut Span<T>) -> R,
+    {
+        let operation_name = operation_name.into();
+        move || {
+            let span = self.handle.map_or_else(Span::inactive, |handle| {
+                handle.follower(operation_name, |options| options.start())
+            });
+            run_in_span(span, f)
+        }
+    }
+}
+
+fn run_in_span<T, F, R>(mut span: Span<T>, f: F) -> R
+where
+    T: Clone + 'static,
+    F: FnOnce(&mut Span<T>) -> R,
+{
+    let _scope = scope::activate(span.handle());
+    f(&mut span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::state::SpanContextState;
+    use crate::Tracer;
+
+    #[test]
+    fn spawn_follower_works() {
+        let (tracer, span_rx) = Tracer::new(AllSampler);
+        let parent: Span<SpanContextState> = tracer.span("parent").start();
+        let parent_span_id = parent.context().unwrap().state().span_id();
+        {
+            let _scope = scope::activate(parent.handle());
+            spawn_follower::<SpanContextState, _, _, _>("follower", |_| {
+                let _grandchild = scope::active::<SpanContextState>()
+                    .unwrap()
+                    .child("grandchild", |options| options.start());
+            })
+            .join()
+            .unwrap();
+        }
+
+        let grandchild = span_rx.try_recv().unwrap();
+        let follower = span_rx.try_recv().unwrap();
+        assert_eq!(follower.operation_name(), "follower");
+        assert!(follower.references()[0].is_follows_from());
+        assert_eq!(follower.references()[0].span().span_id(), parent_span_id);
+        assert_eq!(
+            grandchild.references()[0].span().span_id(),
+            follower.context().state().span_id()
+        );
+
+        // No active span
+        let sampled = spawn_child::<SpanContextState, _, _, _>("orphan", |span| span.is_sampled())
+            .join()
+            .unwrap();
+        assert!(!sampled);
+    }
+}
@@ -1,7 +1,14 @@
 use crate::sampler::Sampler;
-use crate::span::{SpanReceiver, SpanSender, StartSpanOptions};
+use crate::scope;
+use crate::span::{FinishedSpan, SpanLimits, SpanReceiver, SpanSender, StartSpanOptions};
+use crossbeam_channel::{SendTimeoutError, TrySendError};
 use std::borrow::Cow;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 /// Tracer.
 ///
@@ -22,7 +29,7 @@ use std::sync::Arc;
 #[derive(Debug)]
 pub struct Tracer<S, T> {
     sampler: Arc<S>,
-    span_tx: SpanSender<T>,
+    sink: Arc<SpanSink<T>>,
 }
 impl<S: Sampler<T>, T> Tracer<S, T> {
     /// This constructor is mainly for backward compatibility, it has the same interface
@@ -36,34 +43,530 @@ impl<S: Sampler<T>, T> Tracer<S, T> {
 
     /// Makes a new `Tracer` instance.
     pub fn with_sender(sampler: S, span_tx: SpanSender<T>) -> Self {
-        Tracer {
-            sampler: Arc::new(sampler),
-            span_tx,
-        }
+        Self::builder(sampler, span_tx).build()
+    }
+
+    /// Returns a `TracerBuilder` for building a `Tracer` with non-default settings.
+    pub fn builder(sampler: S, span_tx: SpanSender<T>) -> TracerBuilder<S, T> {
+        TracerBuilder::new(sampler, span_tx)
     }
 
     /// Returns `StartSpanOptions` for starting a span which has the name `operation_name`.
-    pub fn span<N>(&self, operation_name: N) -> StartSpanOptions<S, T>
+    pub fn span<N>(&self, operation_name: N) -> StartSpanOptions<'_, S, T>
     where
         N: Into<Cow<'static, str>>,
     {
-        StartSpanOptions::new(operation_name, &self.span_tx, &self.sampler)
+        StartSpanOptions::new(operation_name, &self.sink, &self.sampler)
+    }
+
+    /// Returns `StartSpanOptions` for starting a span which has the name `operation_name`
+    /// as a `ChildOf` the span active for the current thread (see the `scope` module).
+    ///
+    /// If there is no active span or the active span is not sampled,
+    /// it is the same as `span(operation_name)`.
+    pub fn scoped_span<N>(&self, operation_name: N) -> StartSpanOptions<'_, S, T>
+    where
+        N: Into<Cow<'static, str>>,
+        T: Clone + 'static,
+    {
+        let options = self.span(operation_name);
+        if let Some(handle) = scope::active::<T>() {
+            options.child_of(&handle)
+        } else {
+            options
+        }
     }
 }
 impl<S, T> Tracer<S, T> {
+    /// The default value of the maximum number of operations whose dropped spans are counted individually.
+    pub const DEFAULT_MAX_DROPPED_SPAN_OPERATIONS: usize = 2000;
+
     /// Clone with the given `sampler`.
+    ///
+    /// The returned tracer shares the dropped span counters with this one.
     pub fn clone_with_sampler<U: Sampler<T>>(&self, sampler: U) -> Tracer<U, T> {
         Tracer {
             sampler: Arc::new(sampler),
-            span_tx: self.span_tx.clone(),
+            sink: Arc::clone(&self.sink),
         }
     }
+
+    /// Returns the number of the finished spans which could not be sent to the `SpanReceiver`
+    /// because the channel was full or disconnected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustracing::Tracer;
+    /// use rustracing::sampler::AllSampler;
+    ///
+    /// let (span_tx, _span_rx) = crossbeam_channel::bounded(1);
+    /// let tracer = Tracer::with_sender(AllSampler, span_tx);
+    /// for _ in 0..3 {
+    ///     let _span = tracer.span("foo").start_with_state(());
+    /// }
+    /// assert_eq!(tracer.dropped_spans(), 2);
+    /// assert_eq!(tracer.dropped_spans_by_operation()["foo"], 2);
+    /// ```
+    pub fn dropped_spans(&self) -> u64 {
+        self.sink.dropped_spans()
+    }
+
+    /// Returns the number of the dropped spans for each operation name.
+    ///
+    /// The spans of the operations beyond `TracerBuilder::max_dropped_span_operations()`
+    /// are not included (see `dropped_spans_of_untracked_operations()`).
+    pub fn dropped_spans_by_operation(&self) -> HashMap<String, u64> {
+        self.sink.dropped_spans_by_operation()
+    }
+
+    /// Returns the number of the dropped spans whose operations are not counted individually
+    /// because of `TracerBuilder::max_dropped_span_operations()`.
+    pub fn dropped_spans_of_untracked_operations(&self) -> u64 {
+        self.sink.dropped_spans_of_untracked_operations()
+    }
 }
 impl<S, T> Clone for Tracer<S, T> {
     fn clone(&self) -> Self {
         Tracer {
             sampler: Arc::clone(&self.sampler),
-            span_tx: self.span_tx.clone(),
+            sink: Arc::clone(&self.sink),
         }
     }
 }
+
+/// `Tracer` builder.
+pub struct TracerBuilder<S, T> {
+    sampler: S,
+    span_tx: SpanSender<T>,
+    backpressure: BackpressurePolicy<T>,
+    span_limits: SpanLimits,
+    on_dropped_span: Option<DroppedSpanCallback<T>>,
+    max_dropped_span_operations: usize,
+}
+impl<S: Sampler<T>, T> TracerBuilder<S, T> {
+    /// Makes a new `TracerBuilder` instance.
+    pub fn new(sampler: S, span_tx: SpanSender<T>) -> Self {
+        TracerBuilder {
+            sampler,
+            span_tx,
+            backpressure: BackpressurePolicy::DropNewest,
+            span_limits: SpanLimits::default(),
+            on_dropped_span: None,
+            max_dropped_span_operations: Tracer::<S, T>::DEFAULT_MAX_DROPPED_SPAN_OPERATIONS,
+        }
+    }
+
+    /// Sets the policy applied when the channel of finished spans is (nearly) full.
+    ///
+    /// The default value is `BackpressurePolicy::DropNewest`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustracing::sampler::AllSampler;
+    /// use rustracing::{BackpressurePolicy, Tracer};
+    ///
+    /// let (span_tx, span_rx) = crossbeam_channel::bounded(1);
+    /// let tracer = Tracer::builder(AllSampler, span_tx)
+    ///     .backpressure(BackpressurePolicy::DropOldest(span_rx.clone()))
+    ///     .build();
+    /// for name in ["foo", "bar"] {
+    ///     let _span = tracer.span(name).start_with_state(());
+    /// }
+    /// assert_eq!(span_rx.try_recv().unwrap().operation_name(), "bar");
+    /// assert_eq!(tracer.dropped_spans_by_operation()["foo"], 1);
+    /// ```
+    pub fn backpressure(mut self, policy: BackpressurePolicy<T>) -> Self {
+        self.backpressure = policy;
+        self
+    }
+
+    /// Sets the limits on the amount of data recorded in each span.
+    ///
+    /// The default value is `SpanLimits::default()` (i.e., no limits).
+    /// `SpanLimits::recommended()` provides reasonable limits.
+    pub fn span_limits(mut self, limits: SpanLimits) -> Self {
+        self.span_limits = limits;
+        self
+    }
+
+    /// Sets the callback which is invoked each time a finished span is dropped
+    /// because it could not be sent to the `SpanReceiver`.
+    ///
+    /// The callback is invoked in the thread which finished the span,
+    /// so it should return quickly and must not block.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustracing::Tracer;
+    /// use rustracing::sampler::AllSampler;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let dropped = Arc::new(AtomicUsize::new(0));
+    /// let dropped_for_callback = Arc::clone(&dropped);
+    /// let (span_tx, _span_rx) = crossbeam_channel::bounded(0);
+    /// let tracer = Tracer::builder(AllSampler, span_tx)
+    ///     .on_dropped_span(move |_span| {
+    ///         dropped_for_callback.fetch_add(1, Ordering::SeqCst);
+    ///     })
+    ///     .build();
+    /// {
+    ///     let _span = tracer.span("foo").start_with_state(());
+    /// }
+    /// assert_eq!(dropped.load(Ordering::SeqCst), 1);
+    /// ```
+    pub fn on_dropped_span<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&FinishedSpan<T>) + Send + Sync + RefUnwindSafe + UnwindSafe + 'static,
+    {
+        self.on_dropped_span = Some(Box::new(f));
+        self
+    }
+
+    /// Sets the maximum number of operations whose dropped spans are counted individually.
+    ///
+    /// The dropped spans of the operations beyond the limit are counted
+    /// by `Tracer::dropped_spans_of_untracked_operations()`.
+    /// The default value is `Tracer::DEFAULT_MAX_DROPPED_SPAN_OPERATIONS`.
+    pub fn max_dropped_span_operations(mut self, max: usize) -> Self {
+        self.max_dropped_span_operations = max;
+        self
+    }
+
+    /// Builds a `Tracer` instance.
+    pub fn build(self) -> Tracer<S, T> {
+        let sink = SpanSink {
+            span_tx: self.span_tx,
+            backpressure: self.backpressure,
+            span_limits: self.span_limits,
+            on_dropped_span: self.on_dropped_span,
+            dropped_spans: AtomicU64::new(0),
+            dropped_spans_by_operation: Mutex::new(HashMap::new()),
+            dropped_spans_of_untracked_operations: AtomicU64::new(0),
+            max_dropped_span_operations: self.max_dropped_span_operations,
+        };
+        Tracer {
+            sampler: Arc::new(self.sampler),
+            sink: Arc::new(sink),
+        }
+    }
+}
+impl<S: fmt::Debug, T> fmt::Debug for TracerBuilder<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TracerBuilder")
+            .field("sampler", &self.sampler)
+            .field("backpressure", &self.backpressure)
+            .field("span_limits", &self.span_limits)
+            .field("on_dropped_span", &self.on_dropped_span.is_some())
+            .field(
+                "max_dropped_span_operations",
+                &self.max_dropped_span_operations,
+            )
+            .finish()
+    }
+}
+
+/// Policy applied when the channel of finished spans is (nearly) full.
+///
+/// The spans dropped by the policies are counted by `Tracer::dropped_spans()`.
+pub enum BackpressurePolicy<T> {
+    /// Drops the newly finished span.
+    ///
+    /// Finishing a span never blocks.
+    DropNewest,
+
+    /// Blocks the thread finishing a span until the channel has room or the timeout expires.
+    ///
+    /// If the timeout expires, the span is dropped.
+    Block(Duration),
+
+    /// Drops the oldest span in the channel to make room for the newly finished span.
+    ///
+    /// The receiver must be a clone of the receiver of the channel.
+    /// Note that the channel is never disconnected while the tracer holds this receiver.
+    DropOldest(SpanReceiver<T>),
+
+    /// Samples down new spans as the channel fills up.
+    ///
+    /// While the occupancy of the channel (i.e., `len / capacity`) is at most `threshold`,
+    /// spans are sampled as usual.
+    /// Beyond that, the sampled root spans (i.e., spans without references) are kept
+    /// with a probability which linearly decreases to `0.0` at the full occupancy.
+    /// The spans which have references or a positive `sampling.priority` tag are always kept,
+    /// so the traces are dropped as a whole.
+    ///
+    /// Finished spans are sent in the same way as `DropNewest`.
+    /// If the channel is unbounded, this policy has no effect.
+    SampleDown {
+        /// The occupancy (`0.0..=1.0`) at which sampling down starts.
+        threshold: f64,
+    },
+}
+impl<T> fmt::Debug for BackpressurePolicy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackpressurePolicy::DropNewest => write!(f, "DropNewest"),
+            BackpressurePolicy::Block(timeout) => f.debug_tuple("Block").field(timeout).finish(),
+            BackpressurePolicy::DropOldest(_) => write!(f, "DropOldest(_)"),
+            BackpressurePolicy::SampleDown { threshold } => f
+                .debug_struct("SampleDown")
+                .field("threshold", threshold)
+                .finish(),
+        }
+    }
+}
+
+const MAX_DROP_OLDEST_ATTEMPTS: usize = 8;
+
+type DroppedSpanCallback<T> =
+    Box<dyn Fn(&FinishedSpan<T>) + Send + Sync + RefUnwindSafe + UnwindSafe + 'static>;
+
+/// The destination of finished spans, shared by a tracer and the spans started by it.
+pub(crate) struct SpanSink<T> {
+    span_tx: SpanSender<T>,
+    backpressure: BackpressurePolicy<T>,
+    span_limits: SpanLimits,
+    on_dropped_span: Option<DroppedSpanCallback<T>>,
+    dropped_spans: AtomicU64,
+    dropped_spans_by_operation: Mutex<HashMap<String, u64>>,
+    dropped_spans_of_untracked_operations: AtomicU64,
+    max_dropped_span_operations: usize,
+}
+impl<T> SpanSink<T> {
+    /// Sends `span` to the `SpanReceiver` in accordance with the backpressure policy.
+    ///
+    /// Returns `false` if the span is dropped.
+    pub(crate) fn send(&self, span: FinishedSpan<T>) -> bool {
+        let rejected = match self.backpressure {
+            BackpressurePolicy::Block(timeout) => match self.span_tx.send_timeout(span, timeout) {
+                Ok(()) => None,
+                Err(SendTimeoutError::Timeout(span))
+                | Err(SendTimeoutError::Disconnected(span)) => Some(span),
+            },
+            BackpressurePolicy::DropOldest(ref span_rx) => self.send_dropping_oldest(span, span_rx),
+            BackpressurePolicy::DropNewest | BackpressurePolicy::SampleDown { .. } => self
+                .span_tx
+                .try_send(span)
+                .err()
+                .map(TrySendError::into_inner),
+        };
+        if let Some(span) = rejected {
+            self.drop_span(span);
+            false
+        } else {
+            true
+        }
+    }
+
+    pub(crate) fn span_limits(&self) -> &SpanLimits {
+        &self.span_limits
+    }
+
+    /// Returns `true` if a new span should not be sampled to relieve the pressure on the channel.
+    pub(crate) fn should_sample_down(&self) -> bool {
+        let threshold = match self.backpressure {
+            BackpressurePolicy::SampleDown { threshold } => threshold.clamp(0.0, 1.0),
+            _ => return false,
+        };
+        let capacity = match self.span_tx.capacity() {
+            Some(capacity) if capacity > 0 => capacity,
+            _ => return false,
+        };
+        let occupancy = self.span_tx.len() as f64 / capacity as f64;
+        if occupancy <= threshold {
+            return false;
+        }
+        let keep_probability = (1.0 - occupancy) / (1.0 - threshold);
+        rand::random::<f64>() >= keep_probability
+    }
+
+    fn send_dropping_oldest(
+        &self,
+        mut span: FinishedSpan<T>,
+        span_rx: &SpanReceiver<T>,
+    ) -> Option<FinishedSpan<T>> {
+        // Other threads may take the freed slot, so retries a few times.
+        for _ in 0..MAX_DROP_OLDEST_ATTEMPTS {
+            match self.span_tx.try_send(span) {
+                Ok(()) => return None,
+                Err(TrySendError::Disconnected(s)) => return Some(s),
+                Err(TrySendError::Full(s)) => {
+                    span = s;
+                    if let Ok(oldest) = span_rx.try_recv() {
+                        self.drop_span(oldest);
+                    }
+                }
+            }
+        }
+        Some(span)
+    }
+
+    fn drop_span(&self, span: FinishedSpan<T>) {
+        self.dropped_spans.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut counts) = self.dropped_spans_by_operation.lock() {
+            if let Some(count) = counts.get_mut(span.operation_name()) {
+                *count += 1;
+            } else if counts.len() < self.max_dropped_span_operations {
+                counts.insert(span.operation_name().to_owned(), 1);
+            } else {
+                self.dropped_spans_of_untracked_operations
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if let Some(f) = self.on_dropped_span.as_ref() {
+            f(&span);
+        }
+    }
+
+    fn dropped_spans(&self) -> u64 {
+        self.dropped_spans.load(Ordering::Relaxed)
+    }
+
+    fn dropped_spans_of_untracked_operations(&self) -> u64 {
+        self.dropped_spans_of_untracked_operations
+            .load(Ordering::Relaxed)
+    }
+
+    fn dropped_spans_by_operation(&self) -> HashMap<String, u64> {
+        self.dropped_spans_by_operation
+            .lock()
+            .map(|counts| counts.clone())
+            .unwrap_or_default()
+    }
+}
+impl<T> fmt::Debug for SpanSink<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpanSink")
+            .field("backpressure", &self.backpressure)
+            .field("span_limits", &self.span_limits)
+            .field("dropped_spans", &self.dropped_spans())
+            .field("on_dropped_span", &self.on_dropped_span.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::span::{Span, SpanHandle};
+
+    #[test]
+    fn tracer_is_unwind_safe() {
+        fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe>() {}
+        assert_unwind_safe::<Tracer<AllSampler, ()>>();
+        assert_unwind_safe::<Span<()>>();
+        assert_unwind_safe::<SpanHandle<()>>();
+    }
+
+    #[test]
+    fn dropped_spans_are_counted() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(1);
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let dropped_for_callback = Arc::clone(&dropped);
+        let tracer = Tracer::builder(AllSampler, span_tx)
+            .on_dropped_span(move |span| {
+                let name = span.operation_name().to_owned();
+                dropped_for_callback.lock().unwrap().push(name);
+            })
+            .build();
+        {
+            let span = tracer.span("first").start_with_state(());
+            let _child = span.child("second", |options| options.start_with_state(()));
+            let _ = tracer.span("third").start_with_state(());
+        }
+        assert_eq!(span_rx.len(), 1);
+        assert_eq!(tracer.dropped_spans(), 2);
+        assert_eq!(*dropped.lock().unwrap(), ["second", "first"]);
+
+        let counts = tracer.dropped_spans_by_operation();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["first"], 1);
+        assert_eq!(counts["second"], 1);
+
+        drop(span_rx);
+        let other = tracer.clone_with_sampler(AllSampler);
+        let _ = other.span("first").start_with_state(());
+        assert_eq!(tracer.dropped_spans(), 3);
+        assert_eq!(tracer.dropped_spans_by_operation()["first"], 2);
+
+        let (span_tx, _) = crossbeam_channel::bounded(0);
+        let tracer = Tracer::builder(AllSampler, span_tx)
+            .max_dropped_span_operations(1)
+            .build();
+        for name in ["first", "other", "third", "first"] {
+            let _ = tracer.span(name).start_with_state(());
+        }
+        let counts = tracer.dropped_spans_by_operation();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts["first"], 2);
+        assert_eq!(tracer.dropped_spans_of_untracked_operations(), 2);
+    }
+
+    #[test]
+    fn block_policy_works() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(1);
+        let tracer = Tracer::builder(AllSampler, span_tx.clone())
+            .backpressure(BackpressurePolicy::Block(Duration::from_secs(10)))
+            .build();
+        let _ = tracer.span("first").start_with_state(());
+
+        let receiver = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            let first = span_rx.recv().unwrap();
+            let second = span_rx.recv().unwrap();
+            (first, second, span_rx)
+        });
+        let _ = tracer.span("second").start_with_state(());
+        let (first, second, span_rx) = receiver.join().unwrap();
+        assert_eq!(first.operation_name(), "first");
+        assert_eq!(second.operation_name(), "second");
+        assert_eq!(tracer.dropped_spans(), 0);
+
+        let tracer = Tracer::builder(AllSampler, span_tx)
+            .backpressure(BackpressurePolicy::Block(Duration::from_millis(10)))
+            .build();
+        let _ = tracer.span("third").start_with_state(());
+        let _ = tracer.span("fourth").start_with_state(());
+        assert_eq!(tracer.dropped_spans(), 1);
+        assert_eq!(span_rx.len(), 1);
+    }
+
+    #[test]
+    fn sample_down_policy_works() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(2);
+        let tracer = Tracer::builder(AllSampler, span_tx)
+            .backpressure(BackpressurePolicy::SampleDown { threshold: 0.5 })
+            .build();
+        for _ in 0..2 {
+            let span = tracer.span("foo").start_with_state(());
+            assert!(span.is_sampled());
+        }
+        assert!(span_rx.is_full());
+        assert!(!tracer.span("foo").start_with_state(()).is_sampled());
+        let _ = span_rx.try_recv();
+        let _ = span_rx.try_recv();
+
+        let parent = tracer.span("parent").start_with_state(());
+        for _ in 0..2 {
+            let _ = tracer.span("foo").start_with_state(());
+        }
+        assert!(span_rx.is_full());
+        let child = parent.child("child", |options| options.start_with_state(()));
+        assert!(child.is_sampled());
+        let follower = tracer
+            .span("follower")
+            .follows_from(&parent)
+            .start_with_state(());
+        assert!(follower.is_sampled());
+
+        let forced = tracer
+            .span("foo")
+            .tag(crate::tag::StdTag::sampling_priority(1))
+            .start_with_state(());
+        assert!(forced.is_sampled());
+    }
+}